@@ -0,0 +1,90 @@
+use web::{ http, alloc, config, err, manager, request, response, websocket };
+use std::io::Write;
+
+const EXPORT_ROWS: usize = 10_000;
+const EXPORT_BATCH: usize = 500;
+
+struct Export {
+    row: usize,
+}
+
+fn main() {
+    let config = config::ServerConfig::load().unwrap();
+    let allocator = alloc::Allocator::new(config.arena_size);
+    let mut connection = http::Connection::new(&config).unwrap();
+
+    let mut routes = manager::Routes::new();
+    routes
+        .mapping(http::Method::Get, b"/health", health)
+        .stream(b"/export", export)
+        .socket(b"/echo", echo)
+        .channel(b"/events")
+        .mapping(http::Method::Post, b"/events", publish);
+
+    connection.handle_connections(&allocator, &routes).unwrap();
+}
+
+fn publish<'r>(context: &mut manager::Context, request: &request::Request, arena: &'r alloc::Allocator<'r>) -> Result<response::HttpResponse<'r>, err::Error> {
+    let bearer = request.get(b"Authorization").and_then(|a| a.strip_prefix(b"Bearer "));
+    let authorized = std::env::var("PUBLISH_TOKEN").is_ok_and(|token| bearer == Some(token.as_bytes()));
+
+    let Some(channel) = context.channel(request.header().end_point()).filter(|_| authorized) else {
+        return response::HttpResponse::new(http::Version::OneOne, response::HttpStatus::Error, http::Content::None, arena);
+    };
+
+    let name = request.get(b"X-Event").and_then(|n| std::str::from_utf8(n).ok());
+    let data = std::str::from_utf8(request.body()).map_err(|_| err::Error::Parsing)?;
+
+    channel.publish(name, data);
+
+    response::HttpResponse::new(http::Version::OneOne, response::HttpStatus::Ok, http::Content::Json(b"{\"status\":\"ok\"}"), arena)
+}
+
+fn health<'r>(_: &mut manager::Context, _: &request::Request, arena: &'r alloc::Allocator<'r>) -> Result<response::HttpResponse<'r>, err::Error> {
+    response::HttpResponse::new(http::Version::OneOne, response::HttpStatus::Ok, http::Content::Json(b"{\"status\":\"ok\"}"), arena)
+}
+
+fn export(_: &mut manager::Context, request: &request::Request) -> Result<(response::ChunkedResponse, Box<dyn manager::Producer>), err::Error> {
+    let headers = [http::Header::new(b"Content-Disposition", b"attachment; filename=\"export.csv\"")];
+    let response = response::ChunkedResponse::new(request.header().version(), response::HttpStatus::Ok, &headers, b"text/csv", &[b"X-Row-Count"]);
+
+    Ok((response, Box::new(Export { row: 0 })))
+}
+
+impl manager::Producer for Export {
+    fn produce(&mut self, _: &mut manager::Context, response: &mut response::ChunkedResponse) -> Result<(), err::Error> {
+        if self.row == 0 {
+            response.write_chunk(b"row,square\n");
+        }
+
+        let mut batch = [0; EXPORT_BATCH * 32];
+        let mut cursor = std::io::Cursor::new(&mut batch[..]);
+
+        for row in self.row..(self.row + EXPORT_BATCH).min(EXPORT_ROWS) {
+            writeln!(cursor, "{},{}", row + 1, (row + 1) * (row + 1)).map_err(|_| err::Error::OutOfBounds)?;
+        }
+
+        let length = cursor.position() as usize;
+        response.write_chunk(&batch[..length]);
+        self.row = (self.row + EXPORT_BATCH).min(EXPORT_ROWS);
+
+        if self.row == EXPORT_ROWS {
+            let mut count = [0; 20];
+            let mut cursor = std::io::Cursor::new(&mut count[..]);
+            write!(cursor, "{}", self.row).map_err(|_| err::Error::OutOfBounds)?;
+
+            let length = cursor.position() as usize;
+            response.finish(&[http::Header::new(b"X-Row-Count", &count[..length])]);
+        }
+
+        Ok(())
+    }
+}
+
+fn echo(_: &mut manager::Context, socket: &mut websocket::Socket) -> Result<(), err::Error> {
+    while let Some(message) = socket.recv() {
+        socket.send(message);
+    }
+
+    Ok(())
+}
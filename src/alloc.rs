@@ -1,70 +1,1022 @@
-use crate::err;
+use crate::{collection, err};
+use std::alloc::GlobalAlloc;
 
-pub struct Allocator {
-    bytes: *mut u8,
+const CHUNK_ALIGN: usize = 16;
+const CHUNK_HEADER: usize = 16;
+const OCCUPIED: usize = usize::MAX;
+#[cfg(feature = "alloc-debug")]
+const DEBUG_HEADER: usize = 32;
+#[cfg(feature = "alloc-debug")]
+const CANARY: [u8; 8] = [0xca, 0xfe, 0xba, 0xbe, 0xde, 0xad, 0xbe, 0xef];
+#[cfg(feature = "alloc-debug")]
+const POISON: u8 = 0xdd;
+
+pub struct Pool<'a> {
+    free: collection::Array<'a, Allocator<'a>>,
+}
+
+pub struct Slab<'a, T> {
+    slots: *mut std::mem::MaybeUninit<T>,
+    next: *mut usize,
     capacity: usize,
-    end: usize,
+    free: usize,
+    len: usize,
+    allocator: std::marker::PhantomData<&'a Allocator<'a>>,
+}
+
+pub struct Allocator<'a> {
+    bytes: std::cell::Cell<*mut u8>,
+    capacity: std::cell::Cell<usize>,
+    end: std::cell::Cell<usize>,
+    chunks: std::cell::Cell<*mut u8>,
+    retired: std::cell::Cell<usize>,
+    peak: std::cell::Cell<usize>,
+    allocations: std::cell::Cell<usize>,
+    failures: std::cell::Cell<usize>,
+    children: std::cell::Cell<*const Node>,
+    #[cfg(feature = "alloc-debug")]
+    last: std::cell::Cell<*mut u8>,
+    report: *const Node,
+    label: &'static str,
+    first: *mut u8,
+    first_capacity: usize,
+    limit: usize,
+    owned: bool,
+    parent: std::marker::PhantomData<&'a ()>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub used: usize,
+    pub total: usize,
+    pub peak: usize,
+    pub allocations: usize,
+    pub failures: usize,
+}
+
+struct Node {
+    label: std::cell::Cell<&'static str>,
+    stats: std::cell::Cell<Stats>,
+    children: std::cell::Cell<*const Node>,
+    next: *const Node,
+}
+
+pub struct ArenaBox<'a, T> {
+    ptr: std::ptr::NonNull<T>,
+    marker: std::marker::PhantomData<&'a mut T>,
 }
 
-impl Allocator {
-    pub fn new(capacity: usize) -> Allocator {
-        let bytes = unsafe {std::alloc::alloc(std::alloc::Layout::from_size_align_unchecked(capacity, 1)) };
+#[derive(Clone, Copy)]
+pub struct Handle<'a> {
+    allocator: &'a Allocator<'a>,
+}
+
+pub struct Global {
+    allocator: std::sync::Mutex<Allocator<'static>>,
+}
+
+impl<'a> Allocator<'a> {
+    pub fn new(capacity: usize) -> Allocator<'static> {
+        let bytes = unsafe { std::alloc::System.alloc(std::alloc::Layout::from_size_align_unchecked(capacity, 1)) };
+
+        let mut allocator = Allocator::from_raw(bytes, capacity);
+        allocator.owned = !bytes.is_null();
+
+        allocator
+    }
+
+    pub const fn empty(limit: usize) -> Allocator<'static> {
+        Allocator::from_raw(std::ptr::null_mut(), 0).with_limit(limit)
+    }
+
+    pub fn child(&self, capacity: usize) -> Result<Allocator<'_>, err::Error> {
+        let node: *mut Node = self.alloc(1)?;
+        let bytes = self.alloc(capacity)?;
 
+        unsafe {
+            node.write(Node {
+                label: std::cell::Cell::new(""),
+                stats: std::cell::Cell::new(Stats::default()),
+                children: std::cell::Cell::new(std::ptr::null()),
+                next: self.children.get(),
+            });
+        }
+
+        self.children.set(node);
+        self.sync();
+
+        let mut child = Allocator::from_raw(bytes, capacity);
+        child.report = node;
+        child.sync();
+
+        Ok(child)
+    }
+
+    const fn from_raw<'p>(bytes: *mut u8, capacity: usize) -> Allocator<'p> {
         Allocator {
-            bytes,
-            capacity,
-            end: 0,
+            bytes: std::cell::Cell::new(bytes),
+            capacity: std::cell::Cell::new(capacity),
+            end: std::cell::Cell::new(0),
+            chunks: std::cell::Cell::new(std::ptr::null_mut()),
+            retired: std::cell::Cell::new(0),
+            peak: std::cell::Cell::new(0),
+            allocations: std::cell::Cell::new(0),
+            failures: std::cell::Cell::new(0),
+            children: std::cell::Cell::new(std::ptr::null()),
+            #[cfg(feature = "alloc-debug")]
+            last: std::cell::Cell::new(std::ptr::null_mut()),
+            report: std::ptr::null(),
+            label: "arena",
+            first: bytes,
+            first_capacity: capacity,
+            limit: capacity,
+            owned: false,
+            parent: std::marker::PhantomData,
         }
     }
 
-    pub fn child(&mut self, capacity: usize) -> Result<Allocator, err::Error> {
-        Ok(Allocator {
-            bytes: self.alloc(capacity)?,
-            capacity,
-            end: 0,
+    pub const fn with_limit(mut self, limit: usize) -> Allocator<'a> {
+        self.limit = if limit > self.first_capacity { limit } else { self.first_capacity };
+        self
+    }
+
+    pub fn with_label(mut self, label: &'static str) -> Allocator<'a> {
+        self.label = label;
+        self.sync();
+        self
+    }
+
+    pub fn alloc<T>(&self, count: usize) -> Result<*mut T, err::Error> {
+        let layout = std::alloc::Layout::array::<T>(count).map_err(|_| err::Error::Allocation)?;
+
+        Ok(self.alloc_layout(layout)? as *mut T)
+    }
+
+    pub fn alloc_layout(&self, layout: std::alloc::Layout) -> Result<*mut u8, err::Error> {
+        if layout.size() == 0 {
+            return Ok(std::ptr::null_mut::<u8>().wrapping_add(layout.align()));
+        }
+
+        let result = self.place(layout);
+
+        match result {
+            Ok(_) => self.allocations.set(self.allocations.get() + 1),
+            Err(_) => self.failures.set(self.failures.get() + 1),
+        }
+
+        self.peak.set(self.peak.get().max(self.used()));
+        self.sync();
+
+        result
+    }
+
+    #[cfg(not(feature = "alloc-debug"))]
+    fn place(&self, layout: std::alloc::Layout) -> Result<*mut u8, err::Error> {
+        self.bump(layout.size(), layout.align())
+    }
+
+    #[cfg(feature = "alloc-debug")]
+    fn place(&self, layout: std::alloc::Layout) -> Result<*mut u8, err::Error> {
+        let header = layout.align().max(DEBUG_HEADER);
+        let ptr = unsafe { self.bump(header + layout.size() + CANARY.len(), header)?.add(header) };
+
+        unsafe {
+            (ptr.sub(3 * std::mem::size_of::<usize>()) as *mut usize).write(header);
+            (ptr.sub(2 * std::mem::size_of::<usize>()) as *mut *mut u8).write(self.last.get());
+            (ptr.sub(std::mem::size_of::<usize>()) as *mut usize).write(layout.size());
+            ptr.add(layout.size()).copy_from_nonoverlapping(CANARY.as_ptr(), CANARY.len());
+        }
+
+        self.last.set(ptr);
+        Ok(ptr)
+    }
+
+    fn bump(&self, size: usize, align: usize) -> Result<*mut u8, err::Error> {
+        let mut offset = self.bytes.get().wrapping_add(self.end.get()).align_offset(align);
+
+        if self.end.get() + offset + size > self.capacity.get() {
+            self.grow(size + align)?;
+            offset = self.bytes.get().wrapping_add(self.end.get()).align_offset(align);
+        }
+
+        let ptr = unsafe { self.bytes.get().add(self.end.get() + offset) };
+        self.end.set(self.end.get() + offset + size);
+
+        Ok(ptr)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice<T: Copy>(&self, count: usize, value: T) -> Result<&mut [T], err::Error> {
+        let ptr = self.alloc::<T>(count)?;
+
+        for i in 0..count {
+            unsafe { ptr.add(i).write(value) };
+        }
+
+        Ok(unsafe { std::slice::from_raw_parts_mut(ptr, count) })
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub fn copy_slice<T: Copy>(&self, items: &[T]) -> Result<&mut [T], err::Error> {
+        let ptr = self.alloc::<T>(items.len())?;
+        unsafe { ptr.copy_from_nonoverlapping(items.as_ptr(), items.len()) };
+
+        Ok(unsafe { std::slice::from_raw_parts_mut(ptr, items.len()) })
+    }
+
+    pub fn boxed<T>(&self, value: T) -> Result<ArenaBox<'_, T>, err::Error> {
+        let ptr = self.alloc::<T>(1)?;
+        unsafe { ptr.write(value) };
+
+        Ok(ArenaBox {
+            ptr: std::ptr::NonNull::new(ptr).ok_or(err::Error::Allocation)?,
+            marker: std::marker::PhantomData,
         })
     }
 
-    pub fn alloc<T>(&mut self, count: usize) -> Result<*mut T, err::Error> {
-        let layout = std::alloc::Layout::from_size_align(count * std::mem::size_of::<T>(), std::mem::align_of::<T>()).map_err(|_| err::Error::Allocation)?;
-        let size = layout.size();
-        let align = layout.align();
+    /// # Safety
+    ///
+    /// `ptr` must come from this allocator and hold room for `count` elements, and nothing
+    /// may use that memory afterwards: freeing the last allocation hands its bytes out again.
+    pub unsafe fn dealloc<T>(&self, ptr: *mut T, count: usize) {
+        self.dealloc_bytes(ptr as *mut u8, count * std::mem::size_of::<T>());
+    }
 
-        if self.end + size > self.capacity {
-            return Err(err::Error::Allocation);
+    /// # Safety
+    ///
+    /// `ptr` must come from this allocator and hold `count` initialized elements.
+    pub unsafe fn realloc<T>(&self, ptr: *mut T, count: usize, new_count: usize) -> Result<*mut T, err::Error> {
+        let layout = std::alloc::Layout::array::<T>(new_count).map_err(|_| err::Error::Allocation)?;
+
+        if new_count <= count || self.extend_last(ptr as *mut u8, count * std::mem::size_of::<T>(), layout.size()) {
+            return Ok(ptr);
         }
 
-        let ptr = unsafe { self.bytes.add(self.end) };
-        let offset = ptr.align_offset(align);
-        self.end += offset + size;
+        let new = self.alloc_layout(layout)? as *mut T;
+        new.copy_from_nonoverlapping(ptr, count);
+        self.dealloc(ptr, count);
 
-        Ok(unsafe {ptr.add(offset) as *mut T})
+        Ok(new)
     }
 
-    pub fn dealloc<T>(&mut self, ptr: *mut T, count: usize) {
-        let layout = unsafe { std::alloc::Layout::from_size_align_unchecked(count * std::mem::size_of::<T>(), std::mem::align_of::<T>()) };
-        let size = layout.size();
-        let bytes = ptr as *mut u8;
+    #[cfg(not(feature = "alloc-debug"))]
+    fn extend_last(&self, bytes: *mut u8, size: usize, new_size: usize) -> bool {
+        let start = self.end.get().wrapping_sub(size);
 
-        if unsafe {self.bytes.add(self.end) == bytes.add(size) } {
-            self.end -= size;
+        if size == 0 || self.bytes.get().wrapping_add(start) != bytes || start + new_size > self.capacity.get() {
+            return false;
         }
+
+        self.end.set(start + new_size);
+        self.peak.set(self.peak.get().max(self.used()));
+        self.sync();
+
+        true
+    }
+
+    #[cfg(feature = "alloc-debug")]
+    fn extend_last(&self, _: *mut u8, _: usize, _: usize) -> bool {
+        false
+    }
+
+    /// # Safety
+    ///
+    /// Same as [`Allocator::dealloc`], with `size` the byte length `bytes` was allocated with.
+    #[cfg(not(feature = "alloc-debug"))]
+    pub unsafe fn dealloc_bytes(&self, bytes: *mut u8, size: usize) {
+        if size > 0 && self.bytes.get().wrapping_add(self.end.get()) == bytes.wrapping_add(size) {
+            self.end.set(self.end.get() - size);
+            self.sync();
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Same as [`Allocator::dealloc`], with `size` the byte length `bytes` was allocated with.
+    #[cfg(feature = "alloc-debug")]
+    pub unsafe fn dealloc_bytes(&self, bytes: *mut u8, size: usize) {
+        if size == 0 {
+            return;
+        }
+
+        assert!(canary_intact(bytes), "{}: write past the end of a {} byte allocation", self.label, size);
+        bytes.write_bytes(POISON, size);
+
+        if bytes != self.last.get() {
+            return;
+        }
+
+        let header = (bytes.sub(3 * std::mem::size_of::<usize>()) as *const usize).read();
+        self.last.set((bytes.sub(2 * std::mem::size_of::<usize>()) as *const *mut u8).read());
+
+        if self.bytes.get().wrapping_add(self.end.get()) == bytes.add(size + CANARY.len()) {
+            self.end.set(self.end.get() - header - size - CANARY.len());
+        }
+
+        self.sync();
+    }
+
+    #[cfg(feature = "alloc-debug")]
+    pub fn check(&self) -> bool {
+        let mut ptr = self.last.get();
+
+        while !ptr.is_null() {
+            if !unsafe { canary_intact(ptr) } {
+                return false;
+            }
+
+            ptr = unsafe { (ptr.sub(2 * std::mem::size_of::<usize>()) as *const *mut u8).read() };
+        }
+
+        true
+    }
+
+    fn grow(&self, needed: usize) -> Result<(), err::Error> {
+        let remaining = self.limit - self.total();
+        let size = (needed + CHUNK_HEADER).max(self.capacity.get().saturating_mul(2)).min(remaining);
+
+        if size < needed + CHUNK_HEADER {
+            return Err(err::Error::Allocation);
+        }
+
+        let layout = std::alloc::Layout::from_size_align(size, CHUNK_ALIGN).map_err(|_| err::Error::Allocation)?;
+        let chunk = unsafe { std::alloc::System.alloc(layout) };
+
+        if chunk.is_null() {
+            return Err(err::Error::Allocation);
+        }
+
+        unsafe {
+            (chunk as *mut *mut u8).write(self.chunks.get());
+            (chunk.add(std::mem::size_of::<usize>()) as *mut usize).write(size);
+        }
+
+        self.chunks.set(chunk);
+        self.retired.set(self.retired.get() + self.end.get());
+        self.bytes.set(unsafe { chunk.add(CHUNK_HEADER) });
+        self.capacity.set(size - CHUNK_HEADER);
+        self.end.set(0);
+
+        Ok(())
     }
 
     pub fn capacity(&self) -> usize {
-        self.capacity
+        self.capacity.get()
     }
 
     pub fn bytes(&self) -> *mut u8 {
-        self.bytes
+        self.bytes.get()
     }
 
     pub fn free_size(&self) -> usize {
-        self.capacity - self.end
+        self.capacity.get() - self.end.get()
+    }
+
+    pub fn total(&self) -> usize {
+        let mut total = self.first_capacity;
+        let mut chunk = self.chunks.get();
+
+        while !chunk.is_null() {
+            unsafe {
+                total += (chunk.add(std::mem::size_of::<usize>()) as *const usize).read();
+                chunk = (chunk as *const *mut u8).read();
+            }
+        }
+
+        total
+    }
+
+    pub fn used(&self) -> usize {
+        self.retired.get() + self.end.get()
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            used: self.used(),
+            total: self.total(),
+            peak: self.peak.get(),
+            allocations: self.allocations.get(),
+            failures: self.failures.get(),
+        }
+    }
+
+    pub fn visit(&self, mut visitor: impl FnMut(usize, &str, Stats)) {
+        visitor(0, self.label, self.stats());
+        visit_nodes(self.children.get(), 1, &mut visitor);
     }
 
     pub fn clear(&mut self) {
-        self.end = 0;
+        #[cfg(feature = "alloc-debug")]
+        {
+            assert!(self.check(), "{}: write past the end of an allocation", self.label);
+            self.last.set(std::ptr::null_mut());
+
+            if !self.first.is_null() {
+                unsafe { self.first.write_bytes(POISON, self.first_capacity) };
+            }
+        }
+
+        self.release_chunks();
+
+        self.bytes.set(self.first);
+        self.capacity.set(self.first_capacity);
+        self.end.set(0);
+        self.retired.set(0);
+        self.children.set(std::ptr::null());
+        self.sync();
+    }
+
+    fn release_chunks(&mut self) {
+        while !self.chunks.get().is_null() {
+            unsafe {
+                let chunk = self.chunks.get();
+                let size = (chunk.add(std::mem::size_of::<usize>()) as *const usize).read();
+                self.chunks.set((chunk as *const *mut u8).read());
+
+                #[cfg(feature = "alloc-debug")]
+                chunk.write_bytes(POISON, size);
+
+                std::alloc::System.dealloc(chunk, std::alloc::Layout::from_size_align_unchecked(size, CHUNK_ALIGN));
+            }
+        }
+    }
+
+    fn sync(&self) {
+        if let Some(node) = unsafe { self.report.as_ref() } {
+            node.label.set(self.label);
+            node.stats.set(self.stats());
+            node.children.set(self.children.get());
+        }
     }
 }
 
+impl Drop for Allocator<'_> {
+    fn drop(&mut self) {
+        self.release_chunks();
+
+        if self.owned {
+            unsafe { std::alloc::System.dealloc(self.first, std::alloc::Layout::from_size_align_unchecked(self.first_capacity, 1)) };
+        }
+    }
+}
+
+impl std::fmt::Display for Allocator<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut result = Ok(());
+
+        self.visit(|depth, label, stats| {
+            if result.is_ok() {
+                result = writeln!(f, "{:indent$}{}: {} of {} bytes used, peak {}, {} allocations, {} failed", "", label, stats.used, stats.total, stats.peak, stats.allocations, stats.failures, indent = 2 * depth);
+            }
+        });
+
+        result
+    }
+}
+
+fn visit_nodes(mut node: *const Node, depth: usize, visitor: &mut impl FnMut(usize, &str, Stats)) {
+    while let Some(current) = unsafe { node.as_ref() } {
+        visitor(depth, current.label.get(), current.stats.get());
+        visit_nodes(current.children.get(), depth + 1, visitor);
+
+        node = current.next;
+    }
+}
+
+#[cfg(feature = "alloc-debug")]
+unsafe fn canary_intact(ptr: *mut u8) -> bool {
+    let size = (ptr.sub(std::mem::size_of::<usize>()) as *const usize).read();
+
+    std::slice::from_raw_parts(ptr.add(size), CANARY.len()) == CANARY
+}
+
+impl<'a, T> ArenaBox<'a, T> {
+    pub fn leak(this: ArenaBox<'a, T>) -> &'a mut T {
+        let ptr = this.ptr;
+        std::mem::forget(this);
+
+        unsafe { &mut *ptr.as_ptr() }
+    }
+}
+
+impl<T> std::ops::Deref for ArenaBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> std::ops::DerefMut for ArenaBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for ArenaBox<'_, T> {
+    fn drop(&mut self) {
+        unsafe { self.ptr.as_ptr().drop_in_place() };
+    }
+}
+
+impl<'a> Handle<'a> {
+    pub fn new(allocator: &'a Allocator<'a>) -> Handle<'a> {
+        Handle { allocator }
+    }
+
+    pub fn allocate_bytes(&self, layout: std::alloc::Layout) -> Option<std::ptr::NonNull<[u8]>> {
+        let ptr = self.allocator.alloc_layout(layout).ok()?;
+
+        Some(std::ptr::NonNull::slice_from_raw_parts(std::ptr::NonNull::new(ptr)?, layout.size()))
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must come from [`Handle::allocate_bytes`] on this handle with the same `layout`.
+    pub unsafe fn deallocate_bytes(&self, ptr: std::ptr::NonNull<u8>, layout: std::alloc::Layout) {
+        self.allocator.dealloc_bytes(ptr.as_ptr(), layout.size());
+    }
+}
+
+#[cfg(feature = "nightly")]
+unsafe impl core::alloc::Allocator for Handle<'_> {
+    fn allocate(&self, layout: std::alloc::Layout) -> Result<std::ptr::NonNull<[u8]>, core::alloc::AllocError> {
+        self.allocate_bytes(layout).ok_or(core::alloc::AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: std::alloc::Layout) {
+        self.deallocate_bytes(ptr, layout)
+    }
+}
+
+#[cfg(feature = "allocator-api2")]
+unsafe impl allocator_api2::alloc::Allocator for Handle<'_> {
+    fn allocate(&self, layout: std::alloc::Layout) -> Result<std::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+        self.allocate_bytes(layout).ok_or(allocator_api2::alloc::AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: std::alloc::Layout) {
+        self.deallocate_bytes(ptr, layout)
+    }
+}
+
+impl Global {
+    pub const fn new(limit: usize) -> Global {
+        Global {
+            allocator: std::sync::Mutex::new(Allocator::empty(limit)),
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.allocator.lock().map_or(0, |allocator| allocator.used())
+    }
+}
+
+unsafe impl Sync for Global {}
+
+unsafe impl GlobalAlloc for Global {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        match self.allocator.lock() {
+            Ok(allocator) => allocator.alloc_layout(layout).unwrap_or(std::ptr::null_mut()),
+            Err(_) => std::ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        if let Ok(allocator) = self.allocator.lock() {
+            allocator.dealloc_bytes(ptr, layout.size());
+        }
+    }
+}
+
+impl<'a> Pool<'a> {
+    pub fn new(count: usize, size: usize, limit: usize, parent: &'a Allocator<'a>) -> Result<Pool<'a>, err::Error> {
+        let mut free = collection::Array::new(count, parent)?;
+
+        for _ in 0..count {
+            free.push(parent.child(size)?.with_limit(limit).with_label("request"))?;
+        }
+
+        Ok(Pool { free })
+    }
+
+    pub fn acquire(&mut self) -> Option<Allocator<'a>> {
+        self.free.pop()
+    }
+
+    pub fn release(&mut self, mut allocator: Allocator<'a>) -> Result<(), err::Error> {
+        if self.free.len() == self.free.cap() {
+            return Err(err::Error::OutOfBounds);
+        }
+
+        allocator.clear();
+        self.free.push(allocator)
+    }
+
+    pub fn available(&self) -> usize {
+        self.free.len()
+    }
+}
+
+impl<'a, T> Slab<'a, T> {
+    pub fn new(capacity: usize, allocator: &'a Allocator<'a>) -> Result<Slab<'a, T>, err::Error> {
+        let slots = allocator.alloc(capacity)?;
+        let next: *mut usize = allocator.alloc(capacity)?;
+
+        for i in 0..capacity {
+            unsafe { next.add(i).write(i + 1) };
+        }
+
+        Ok(Slab {
+            slots,
+            next,
+            capacity,
+            free: 0,
+            len: 0,
+            allocator: std::marker::PhantomData,
+        })
+    }
+
+    pub fn insert(&mut self, value: T) -> Result<usize, err::Error> {
+        if self.free == self.capacity {
+            return Err(err::Error::OutOfBounds);
+        }
+
+        let key = self.free;
+
+        unsafe {
+            self.free = self.next.add(key).read();
+            self.next.add(key).write(OCCUPIED);
+            (*self.slots.add(key)).write(value);
+        }
+
+        self.len += 1;
+        Ok(key)
+    }
+
+    pub fn remove(&mut self, key: usize) -> Option<T> {
+        if !self.contains(key) {
+            return None;
+        }
+
+        let value = unsafe { (*self.slots.add(key)).assume_init_read() };
+
+        unsafe { self.next.add(key).write(self.free) };
+        self.free = key;
+        self.len -= 1;
+
+        Some(value)
+    }
+
+    pub fn get(&self, key: usize) -> Option<&T> {
+        self.contains(key).then(|| unsafe { (*self.slots.add(key)).assume_init_ref() })
+    }
+
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut T> {
+        self.contains(key).then(|| unsafe { (*self.slots.add(key)).assume_init_mut() })
+    }
+
+    pub fn contains(&self, key: usize) -> bool {
+        key < self.capacity && unsafe { self.next.add(key).read() } == OCCUPIED
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<T> Drop for Slab<'_, T> {
+    fn drop(&mut self) {
+        for key in 0..self.capacity {
+            if self.contains(key) {
+                unsafe { (*self.slots.add(key)).assume_init_drop() };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_into_chained_chunks_up_to_the_limit() {
+        let allocator = Allocator::new(64).with_limit(1024);
+        let storage = Allocator::new(1024);
+        let mut blocks = collection::Array::new(0, &storage).unwrap();
+
+        loop {
+            match allocator.alloc::<u64>(2) {
+                Ok(ptr) => {
+                    let value = blocks.len() as u64;
+                    unsafe { ptr.write(value) };
+                    blocks.push(ptr).unwrap();
+                }
+                Err(e) => {
+                    assert!(matches!(e, err::Error::Allocation));
+                    break;
+                }
+            }
+
+            assert!(allocator.total() <= allocator.limit());
+            assert!(allocator.used() <= allocator.total());
+        }
+
+        assert!(allocator.total() > 64);
+        assert!(blocks.len() > 4);
+        assert_eq!(allocator.stats().failures, 1);
+
+        for (i, ptr) in blocks.slice().iter().enumerate() {
+            assert_eq!(unsafe { ptr.read() }, i as u64);
+        }
+    }
+
+    #[test]
+    fn refuses_allocations_past_the_limit() {
+        let allocator = Allocator::new(128);
+
+        assert!(matches!(allocator.alloc::<u8>(256), Err(err::Error::Allocation)));
+        assert_eq!(allocator.total(), 128);
+        assert_eq!(allocator.used(), 0);
+
+        let allocator = Allocator::new(128).with_limit(512);
+
+        assert!(allocator.alloc::<u8>(256).is_ok());
+        assert!(matches!(allocator.alloc::<u8>(1024), Err(err::Error::Allocation)));
+        assert!(allocator.total() <= 512);
+    }
+
+    #[test]
+    fn clear_keeps_the_first_chunk() {
+        let mut allocator = Allocator::new(128).with_limit(4096);
+        let first = allocator.bytes();
+
+        allocator.alloc::<u8>(512).unwrap();
+        allocator.alloc::<u8>(512).unwrap();
+        assert_ne!(allocator.bytes(), first);
+        assert!(allocator.total() > 128);
+
+        allocator.clear();
+        assert_eq!(allocator.bytes(), first);
+        assert_eq!(allocator.capacity(), 128);
+        assert_eq!(allocator.total(), 128);
+        assert_eq!(allocator.used(), 0);
+
+        allocator.alloc::<u8>(16).unwrap();
+        assert_eq!(allocator.total(), 128);
+    }
+
+    #[test]
+    fn tracks_used_and_total() {
+        let allocator = Allocator::new(1024);
+        assert_eq!(allocator.total(), 1024);
+        assert_eq!(allocator.used(), 0);
+
+        allocator.alloc::<u64>(4).unwrap();
+        let used = allocator.used();
+        assert!(used >= 32);
+
+        let ptr = allocator.alloc::<u64>(4).unwrap();
+        let grown = allocator.used();
+        assert!(grown >= used + 32);
+
+        unsafe { allocator.dealloc(ptr, 4) };
+        assert!(allocator.used() < grown);
+        assert_eq!(allocator.stats().peak, grown);
+        assert_eq!(allocator.total(), 1024);
+    }
+
+    #[test]
+    fn realloc_extends_in_place_or_copies() {
+        let allocator = Allocator::new(4096);
+        let ptr = allocator.alloc::<u32>(4).unwrap();
+
+        for i in 0..4 {
+            unsafe { ptr.add(i).write(i as u32) };
+        }
+
+        let grown = unsafe { allocator.realloc(ptr, 4, 8) }.unwrap();
+        let other = allocator.alloc::<u32>(1).unwrap();
+        let moved = unsafe { allocator.realloc(grown, 8, 64) }.unwrap();
+
+        assert_ne!(moved, grown);
+        assert_ne!(moved, other);
+        assert_eq!(unsafe { std::slice::from_raw_parts(moved, 4) }, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn child_arenas_are_carved_from_the_parent() {
+        let parent = Allocator::new(4096).with_label("parent");
+        let child = parent.child(1024).unwrap().with_label("child");
+        let used = parent.used();
+        assert!(used >= 1024);
+
+        child.alloc::<u8>(128).unwrap();
+        assert_eq!(parent.used(), used);
+
+        let grandchild = child.child(64).unwrap().with_label("grandchild").with_limit(1024);
+        grandchild.alloc::<u8>(512).unwrap();
+        assert!(grandchild.total() > 64);
+        assert_eq!(parent.used(), used);
+
+        assert!(matches!(parent.child(8192), Err(err::Error::Allocation)));
+    }
+
+    #[test]
+    fn visit_reports_the_arena_tree() {
+        let parent = Allocator::new(4096).with_label("parent");
+        let child = parent.child(512).unwrap().with_label("child");
+        let grandchild = child.child(128).unwrap().with_label("grandchild");
+
+        child.alloc::<u8>(16).unwrap();
+        grandchild.alloc::<u8>(32).unwrap();
+        assert!(grandchild.alloc::<u8>(256).is_err());
+
+        let expected = [(0, "parent"), (1, "child"), (2, "grandchild")];
+        let mut nodes = [Stats::default(); 3];
+        let mut count = 0;
+
+        parent.visit(|depth, label, stats| {
+            assert_eq!((depth, label), expected[count]);
+            nodes[count] = stats;
+            count += 1;
+        });
+
+        assert_eq!(count, 3);
+        assert_eq!(nodes[1].total, 512);
+        assert_eq!(nodes[1].allocations, 3);
+        assert_eq!(nodes[2].total, 128);
+        assert_eq!(nodes[2].allocations, 1);
+        assert_eq!(nodes[2].failures, 1);
+        assert_eq!(nodes[2].used, grandchild.used());
+
+        let report = parent.to_string();
+        assert!(report.starts_with("parent: "));
+        assert!(report.contains("\n  child: "));
+        assert!(report.contains("\n    grandchild: "));
+    }
+
+    #[test]
+    fn stats_count_allocations_failures_and_peak() {
+        let mut allocator = Allocator::new(256);
+
+        allocator.alloc::<u8>(16).unwrap();
+        allocator.alloc::<u8>(16).unwrap();
+        assert!(allocator.alloc::<u8>(1024).is_err());
+        assert!(allocator.alloc::<u8>(0).is_ok());
+
+        let stats = allocator.stats();
+        assert_eq!(stats.allocations, 2);
+        assert_eq!(stats.failures, 1);
+        assert_eq!(stats.used, allocator.used());
+        assert_eq!(stats.total, 256);
+        assert_eq!(stats.peak, stats.used);
+
+        allocator.clear();
+        assert_eq!(allocator.stats().used, 0);
+        assert_eq!(allocator.stats().peak, stats.peak);
+    }
+
+    #[test]
+    fn pool_hands_out_cleared_arenas_up_to_its_size() {
+        let parent = Allocator::new(4096);
+        let mut pool = Pool::new(2, 256, 1024, &parent).unwrap();
+        assert_eq!(pool.available(), 2);
+
+        let first = pool.acquire().unwrap();
+        let second = pool.acquire().unwrap();
+        assert!(pool.acquire().is_none());
+
+        first.alloc::<u8>(512).unwrap();
+        assert!(first.total() > 256);
+
+        pool.release(first).unwrap();
+        pool.release(second).unwrap();
+        assert_eq!(pool.available(), 2);
+
+        let reused = pool.acquire().unwrap();
+        assert_eq!(reused.used(), 0);
+        assert_eq!(reused.total(), 256);
+        assert_eq!(reused.limit(), 1024);
+        pool.release(reused).unwrap();
+
+        let extra = parent.child(64).unwrap();
+        assert!(matches!(pool.release(extra), Err(err::Error::OutOfBounds)));
+        assert_eq!(pool.available(), 2);
+    }
+
+    #[test]
+    fn handle_allocates_from_the_arena() {
+        let allocator = Allocator::new(256);
+        let handle = Handle::new(&allocator);
+        let layout = std::alloc::Layout::from_size_align(24, 8).unwrap();
+
+        let bytes = handle.allocate_bytes(layout).unwrap();
+        assert_eq!(bytes.len(), 24);
+        assert_eq!(bytes.cast::<u8>().as_ptr().align_offset(8), 0);
+
+        let used = allocator.used();
+        unsafe { handle.deallocate_bytes(bytes.cast(), layout) };
+        assert!(allocator.used() < used);
+
+        assert!(handle.allocate_bytes(std::alloc::Layout::from_size_align(1024, 8).unwrap()).is_none());
+    }
+
+    #[cfg(feature = "allocator-api2")]
+    #[test]
+    fn handle_backs_allocator_api2_collections() {
+        let allocator = Allocator::new(4096);
+        let mut values = allocator_api2::vec::Vec::new_in(Handle::new(&allocator));
+        values.extend(0..100u32);
+
+        assert!(values.iter().copied().eq(0..100));
+        assert!(allocator.used() >= 400);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn handle_backs_std_collections() {
+        let allocator = Allocator::new(4096);
+        let mut values = std::vec::Vec::new_in(Handle::new(&allocator));
+        values.extend(0..100u32);
+
+        assert!(values.iter().copied().eq(0..100));
+        assert!(allocator.used() >= 400);
+    }
+
+    #[test]
+    fn global_allocates_up_to_its_limit() {
+        let global = Global::new(4096);
+        let layout = std::alloc::Layout::from_size_align(64, 16).unwrap();
+
+        let ptr = unsafe { global.alloc(layout) };
+        assert!(!ptr.is_null());
+        assert_eq!(ptr.align_offset(16), 0);
+        assert!(global.used() >= 64);
+
+        let used = global.used();
+        unsafe { global.dealloc(ptr, layout) };
+        assert!(global.used() < used);
+
+        assert!(unsafe { global.alloc(std::alloc::Layout::from_size_align(8192, 16).unwrap()) }.is_null());
+    }
+
+    #[cfg(feature = "alloc-debug")]
+    #[test]
+    fn check_detects_a_broken_canary() {
+        let allocator = Allocator::new(1024);
+        let ptr = allocator.alloc::<u8>(16).unwrap();
+        allocator.alloc::<u8>(16).unwrap();
+        assert!(allocator.check());
+
+        unsafe { ptr.add(16).write(0) };
+        assert!(!allocator.check());
+    }
+
+    #[cfg(feature = "alloc-debug")]
+    #[test]
+    #[should_panic(expected = "write past the end")]
+    fn dealloc_panics_on_a_broken_canary() {
+        let allocator = Allocator::new(1024);
+        let ptr = allocator.alloc::<u8>(16).unwrap();
+
+        unsafe {
+            ptr.add(16).write(0);
+            allocator.dealloc(ptr, 16);
+        }
+    }
+
+    #[cfg(feature = "alloc-debug")]
+    #[test]
+    fn dealloc_poisons_freed_memory() {
+        let allocator = Allocator::new(1024);
+        let ptr = allocator.alloc::<u8>(16).unwrap();
+        allocator.alloc::<u8>(16).unwrap();
+
+        unsafe {
+            ptr.write_bytes(0, 16);
+            allocator.dealloc(ptr, 16);
+            assert!(std::slice::from_raw_parts(ptr, 16).iter().all(|&b| b == POISON));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{http, collection, alloc, err};
+use std::io::{Read, Seek};
+
+pub struct Asset<'a> {
+    path: collection::Array<'a, u8>,
+    extension: collection::Array<'a, u8>,
+    identity: collection::Array<'a, u8>,
+    gzip: Option<collection::Array<'a, u8>>,
+    brotli: Option<collection::Array<'a, u8>>,
+}
+
+impl<'a> Asset<'a> {
+    pub fn load(file: std::path::PathBuf, prefix: &[u8], allocator: &'a alloc::Allocator<'a>) -> Result<Asset<'a>, err::Error> {
+        let name = file.file_name().and_then(|n| n.to_str()).ok_or(err::Error::FileNotFound)?.as_bytes();
+        let extension = file.extension().and_then(|e| e.to_str()).unwrap_or_default().as_bytes();
+
+        let mut path = collection::Array::new(prefix.len() + name.len(), allocator)?;
+        path.copy(prefix)?;
+        path.copy(name)?;
+
+        let mut ext = collection::Array::new(extension.len(), allocator)?;
+        ext.copy(extension)?;
+
+        let gzip = read_sibling(&file, "gz", allocator)?;
+        let brotli = read_sibling(&file, "br", allocator)?;
+
+        Ok(Asset {
+            path,
+            extension: ext,
+            identity: read_file(file, allocator)?,
+            gzip,
+            brotli,
+        })
+    }
+
+    pub fn path(&self) -> &[u8] {
+        self.path.slice()
+    }
+
+    pub fn content(&self) -> http::Content<'_> {
+        http::Content::from_extension(self.extension.slice(), self.identity.slice())
+    }
+
+    pub fn encodings(&self) -> [http::Encoding; 2] {
+        [
+            if self.brotli.is_some() { http::Encoding::Brotli } else { http::Encoding::Identity },
+            if self.gzip.is_some() { http::Encoding::Gzip } else { http::Encoding::Identity },
+        ]
+    }
+
+    pub fn encoded(&self, encoding: http::Encoding) -> Option<&[u8]> {
+        match encoding {
+            http::Encoding::Identity => Some(self.identity.slice()),
+            http::Encoding::Gzip => self.gzip.as_ref().map(|b| b.slice()),
+            http::Encoding::Brotli => self.brotli.as_ref().map(|b| b.slice()),
+            http::Encoding::Deflate => None,
+        }
+    }
+}
+
+pub fn is_precompressed(file: &std::path::Path) -> bool {
+    file.extension().is_some_and(|e| e == "gz" || e == "br")
+}
+
+fn read_sibling<'a>(file: &std::path::Path, extension: &str, allocator: &'a alloc::Allocator<'a>) -> Result<Option<collection::Array<'a, u8>>, err::Error> {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+
+    let sibling = std::path::PathBuf::from(name);
+
+    if sibling.is_file() {
+        Ok(Some(read_file(sibling, allocator)?))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn read_file<'a>(path: std::path::PathBuf, allocator: &'a alloc::Allocator<'a>) -> Result<collection::Array<'a, u8>, err::Error> {
+    let mut file = std::fs::File::open(path).map_err(|_| err::Error::FileNotFound)?;
+    file.seek(std::io::SeekFrom::End(0)).map_err(|_| err::Error::OutOfBounds)?;
+    let size = file.stream_position().map_err(|_| err::Error::OutOfBounds)?;
+    file.seek(std::io::SeekFrom::Start(0)).map_err(|_| err::Error::OutOfBounds)?;
+
+    let mut bytes = collection::Array::new(size as usize, allocator)?;
+    bytes.resize(size as usize, 0)?;
+
+    let slice = bytes.slice_mut();
+    let total = file.read(slice).map_err(|_| err::Error::OutOfBounds)?;
+
+    if total != size as usize {
+        Err(err::Error::OutOfBounds)
+    } else {
+        Ok(bytes)
+    }
+}
@@ -1,67 +1,170 @@
-use crate::{err, alloc};
+use crate::{err, alloc, hash};
 
-pub struct Array<T> {
-    ptr: *mut T,
+const LOAD_NUMERATOR: usize = 7;
+const LOAD_DENOMINATOR: usize = 8;
+
+pub struct Array<'a, T> {
+    ptr: std::ptr::NonNull<T>,
     capacity: usize,
     len: usize,
+    allocator: &'a alloc::Allocator<'a>,
+}
+
+pub struct String<'a> {
+    bytes: Array<'a, u8>,
+}
+
+pub struct IntoIter<'a, T> {
+    array: Array<'a, T>,
+    index: usize,
+}
+
+pub struct HashMap<'a, K, V> {
+    buckets: std::ptr::NonNull<std::mem::MaybeUninit<Bucket<K, V>>>,
+    occupied: std::ptr::NonNull<u64>,
+    capacity: usize,
+    len: usize,
+    allocator: &'a alloc::Allocator<'a>,
+    state: hash::RandomState,
+}
+
+struct Bucket<K, V> {
+    hash: usize,
+    key: K,
+    value: V,
+}
+
+pub enum Entry<'m, 'a, K, V> {
+    Occupied(OccupiedEntry<'m, 'a, K, V>),
+    Vacant(VacantEntry<'m, 'a, K, V>),
+}
+
+pub struct OccupiedEntry<'m, 'a, K, V> {
+    map: &'m mut HashMap<'a, K, V>,
+    index: usize,
 }
 
-pub struct HashMap<K, V> {
-    keys: Array<K>,
-    values: Array<V>,
+pub struct VacantEntry<'m, 'a, K, V> {
+    map: &'m mut HashMap<'a, K, V>,
+    hash: usize,
+    key: K,
 }
 
-impl<T> Array<T> {
-    pub fn new(capacity: usize, allocator: &mut alloc::Allocator) -> Result<Array<T>, err::Error> {
+pub struct Iter<'m, 'a, K, V> {
+    map: &'m HashMap<'a, K, V>,
+    index: usize,
+    remaining: usize,
+}
+
+pub struct IterMut<'m, 'a, K, V> {
+    map: &'m mut HashMap<'a, K, V>,
+    index: usize,
+    remaining: usize,
+}
+
+impl<'a, T> Array<'a, T> {
+    pub fn new(capacity: usize, allocator: &'a alloc::Allocator<'a>) -> Result<Array<'a, T>, err::Error> {
         Ok(Array::<T> {
-            ptr: allocator.alloc(capacity)?,
+            ptr: std::ptr::NonNull::new(allocator.alloc(capacity)?).ok_or(err::Error::Allocation)?,
             capacity,
             len: 0,
+            allocator,
         })
     }
 
+    pub fn reserve(&mut self, additional: usize) -> Result<(), err::Error> {
+        let needed = self.len.checked_add(additional).ok_or(err::Error::Allocation)?;
+
+        if needed <= self.capacity {
+            return Ok(());
+        }
+
+        let capacity = needed.max(self.capacity * 2).max(4);
+        self.ptr = std::ptr::NonNull::new(unsafe { self.allocator.realloc(self.ptr.as_ptr(), self.capacity, capacity)? }).ok_or(err::Error::Allocation)?;
+        self.capacity = capacity;
+
+        Ok(())
+    }
+
     pub fn copy(&mut self, dst: &[T]) -> Result<(), err::Error> where T: Copy {
-        if self.len + dst.len() > self.capacity {
-            Err(err::Error::OutOfBounds)
-        } else {
-            let src = unsafe { std::slice::from_raw_parts_mut(self.ptr.add(self.len), dst.len())};
-            src.copy_from_slice(dst);
+        self.reserve(dst.len())?;
 
-            self.len += dst.len();
+        unsafe { self.ptr.as_ptr().add(self.len).copy_from_nonoverlapping(dst.as_ptr(), dst.len()) };
+        self.len += dst.len();
 
-            Ok(())
-        }
+        Ok(())
     }
 
     pub fn push(&mut self, item: T) -> Result<(), err::Error> {
-        if self.len >= self.capacity {
-            Err(err::Error::OutOfBounds)
-        } else {
-            unsafe { self.ptr.add(self.len).write(item) };
-            self.len += 1;
+        self.reserve(1)?;
 
-            Ok(())
-        }
+        unsafe { self.ptr.as_ptr().add(self.len).write(item) };
+        self.len += 1;
+
+        Ok(())
     }
 
     pub fn append_slice(&mut self, items: &[T]) -> Result<(), err::Error> where T: Copy {
-        if self.len + items.len() > self.capacity {
-            Err(err::Error::OutOfBounds)
-        } else {
-            for i in self.len..items.len() + self.len {
-                unsafe { self.ptr.add(i).write(items[i - self.len]) };
-            }
+        self.copy(items)
+    }
+
+    pub fn try_extend<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Result<(), err::Error> {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0)?;
 
-            self.len += items.len();
-            Ok(())
+        for item in iter {
+            self.push(item)?;
         }
+
+        Ok(())
     }
 
     pub fn insert(&mut self, index: usize, item: T) -> Result<(), err::Error> {
-        if index >= self.capacity {
-            Err(err::Error::OutOfBounds)
-        } else {
-            Ok(unsafe { self.ptr.add(index).write(item) })
+        if index > self.len {
+            return Err(err::Error::OutOfBounds);
+        }
+
+        self.reserve(1)?;
+
+        unsafe {
+            let at = self.ptr.as_ptr().add(index);
+            at.copy_to(at.add(1), self.len - index);
+            at.write(item);
+        }
+
+        self.len += 1;
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<T, err::Error> {
+        if index >= self.len {
+            return Err(err::Error::OutOfBounds);
+        }
+
+        self.len -= 1;
+
+        unsafe {
+            let at = self.ptr.as_ptr().add(index);
+            let item = at.read();
+            at.copy_from(at.add(1), self.len - index);
+
+            Ok(item)
+        }
+    }
+
+    pub fn swap_remove(&mut self, index: usize) -> Result<T, err::Error> {
+        if index >= self.len {
+            return Err(err::Error::OutOfBounds);
+        }
+
+        self.len -= 1;
+
+        unsafe {
+            let item = self.ptr.as_ptr().add(index).read();
+            self.ptr.as_ptr().add(index).copy_from(self.ptr.as_ptr().add(self.len), 1);
+
+            Ok(item)
         }
     }
 
@@ -70,140 +173,1041 @@ impl<T> Array<T> {
             None
         } else {
             self.len -= 1;
-            Some(unsafe { self.ptr.add(self.len).read() })
+            Some(unsafe { self.ptr.as_ptr().add(self.len).read() })
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
         }
+
+        let tail = std::ptr::slice_from_raw_parts_mut(unsafe { self.ptr.as_ptr().add(len) }, self.len - len);
+        self.len = len;
+
+        unsafe { std::ptr::drop_in_place(tail) };
+    }
+
+    pub fn resize(&mut self, len: usize, value: T) -> Result<(), err::Error> where T: Clone {
+        if len <= self.len {
+            self.truncate(len);
+            return Ok(());
+        }
+
+        self.reserve(len - self.len)?;
+
+        while self.len < len {
+            unsafe { self.ptr.as_ptr().add(self.len).write(value.clone()) };
+            self.len += 1;
+        }
+
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
     }
 
     pub fn len(&self) -> usize {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     pub fn cap(&self) -> usize {
         self.capacity
     }
 
-    pub fn zero(&mut self) {
-        unsafe { self.ptr.write_bytes(0, self.capacity) };
-        self.len = self.capacity;
+    pub fn at(&self, index: usize) -> Result<&T, err::Error> {
+        self.slice().get(index).ok_or(err::Error::OutOfBounds)
     }
 
-    pub fn at(&self, index: usize) -> Result<T, err::Error> {
-        if index >= self.capacity {
-            Err(err::Error::OutOfBounds)
-        } else {
-            Ok(unsafe { self.ptr.add(index).read() })
+    pub fn slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    pub fn slice_mut(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T> Drop for Array<'_, T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T> std::ops::Deref for Array<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.slice()
+    }
+}
+
+impl<T> std::ops::DerefMut for Array<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.slice_mut()
+    }
+}
+
+impl<T, I: std::slice::SliceIndex<[T]>> std::ops::Index<I> for Array<'_, T> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &I::Output {
+        &self.slice()[index]
+    }
+}
+
+impl<T, I: std::slice::SliceIndex<[T]>> std::ops::IndexMut<I> for Array<'_, T> {
+    fn index_mut(&mut self, index: I) -> &mut I::Output {
+        &mut self.slice_mut()[index]
+    }
+}
+
+impl<'a, T> IntoIterator for Array<'a, T> {
+    type Item = T;
+    type IntoIter = IntoIter<'a, T>;
+
+    fn into_iter(self) -> IntoIter<'a, T> {
+        IntoIter {
+            array: self,
+            index: 0,
         }
     }
+}
 
-    pub fn eq(&self, other: &Array<T>) -> bool where T: Eq {
-        let self_slice = self.slice();
-        let other_slice = other.slice();
-        let length = self_slice.len();
+impl<'b, T> IntoIterator for &'b Array<'_, T> {
+    type Item = &'b T;
+    type IntoIter = std::slice::Iter<'b, T>;
 
-        if length != other_slice.len() {
-            false
-        } else {
-            for i in 0..length {
-                if self_slice[i] != other_slice[i] {
-                    return false;
-                }
-            }
+    fn into_iter(self) -> std::slice::Iter<'b, T> {
+        self.slice().iter()
+    }
+}
 
-            true
+impl<'b, T> IntoIterator for &'b mut Array<'_, T> {
+    type Item = &'b mut T;
+    type IntoIter = std::slice::IterMut<'b, T>;
+
+    fn into_iter(self) -> std::slice::IterMut<'b, T> {
+        self.slice_mut().iter_mut()
+    }
+}
+
+impl<T> Iterator for IntoIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.index >= self.array.len {
+            return None;
         }
+
+        self.index += 1;
+        Some(unsafe { self.array.ptr.as_ptr().add(self.index - 1).read() })
     }
 
-    pub fn slice(&self) -> &[T] {
-        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.array.len - self.index;
+        (remaining, Some(remaining))
     }
+}
 
-    pub fn slice_mut(&mut self) -> &mut [T] {
-        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+impl<T> ExactSizeIterator for IntoIter<'_, T> {}
+
+impl<T> Drop for IntoIter<'_, T> {
+    fn drop(&mut self) {
+        let rest = std::ptr::slice_from_raw_parts_mut(unsafe { self.array.ptr.as_ptr().add(self.index) }, self.array.len - self.index);
+        self.array.len = 0;
+
+        unsafe { std::ptr::drop_in_place(rest) };
     }
 }
 
-impl Array<u8> {
+impl<T: PartialEq> PartialEq for Array<'_, T> {
+    fn eq(&self, other: &Array<'_, T>) -> bool {
+        self.slice() == other.slice()
+    }
+}
+
+impl Array<'_, u8> {
     pub fn parse(&mut self, value: usize) -> Result<(), err::Error> {
         let l = self.len();
         let mut n = value;
 
+        if n == 0 {
+            return self.push(b'0');
+        }
+
         while n > 0 {
             let rest = n % 10;
             n /= 10;
             self.push(rest as u8 + b'0')?;
         }
 
-        for i in l..self.len {
+        for i in l..l + (self.len - l) / 2 {
             let last = self.len - (i - l) - 1;
-            unsafe { std::mem::swap(&mut *self.ptr.add(i), &mut *self.ptr.add(last)) };
+            unsafe { std::ptr::swap(self.ptr.as_ptr().add(i), self.ptr.as_ptr().add(last)) };
         }
 
         Ok(())
     }
 }
 
-pub trait Hash {
-    fn hash(&self) -> usize;
-    fn eq(&self, other: &Self) -> bool;
-    fn is_zero(&self) -> bool;
+impl<'a> String<'a> {
+    pub fn new(capacity: usize, allocator: &'a alloc::Allocator<'a>) -> Result<String<'a>, err::Error> {
+        Ok(String {
+            bytes: Array::new(capacity, allocator)?,
+        })
+    }
+
+    pub fn from_str(value: &str, allocator: &'a alloc::Allocator<'a>) -> Result<String<'a>, err::Error> {
+        let mut string = String::new(value.len(), allocator)?;
+        string.push_str(value)?;
+
+        Ok(string)
+    }
+
+    pub fn from_utf8(bytes: Array<'a, u8>) -> Result<String<'a>, err::Error> {
+        std::str::from_utf8(bytes.slice()).map_err(|_| err::Error::Utf8)?;
+
+        Ok(String { bytes })
+    }
+
+    pub fn push(&mut self, c: char) -> Result<(), err::Error> {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    pub fn push_str(&mut self, value: &str) -> Result<(), err::Error> {
+        self.bytes.copy(value.as_bytes())
+    }
+
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), err::Error> {
+        self.push_str(std::str::from_utf8(bytes).map_err(|_| err::Error::Utf8)?)
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.as_str().chars().next_back()?;
+        self.bytes.truncate(self.len() - c.len_utf8());
+
+        Some(c)
+    }
+
+    pub fn truncate(&mut self, len: usize) -> Result<(), err::Error> {
+        if !self.as_str().is_char_boundary(len) {
+            return Err(err::Error::Utf8);
+        }
+
+        self.bytes.truncate(len);
+
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn as_str(&self) -> &str {
+        unsafe { std::str::from_utf8_unchecked(self.bytes.slice()) }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.slice()
+    }
+
+    pub fn into_bytes(self) -> Array<'a, u8> {
+        self.bytes
+    }
 }
 
-impl<K: Hash, V> HashMap<K, V> {
-    pub fn new(capacity: usize, allocator: &mut alloc::Allocator) -> Result<HashMap<K, V>, err::Error> {
-        let mut keys = Array::new(capacity, allocator)?;
-        let mut values = Array::new(capacity, allocator)?;
+impl std::ops::Deref for String<'_> {
+    type Target = str;
 
-        keys.zero();
-        values.zero();
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
 
-        Ok(HashMap::<K, V> {
-            keys,
-            values,
-        })
+impl std::fmt::Write for String<'_> {
+    fn write_str(&mut self, value: &str) -> std::fmt::Result {
+        self.push_str(value).map_err(|_| std::fmt::Error)
     }
+}
+
+impl std::fmt::Display for String<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
-    pub fn insert(&mut self, key: K, value: V) -> Result<(), err::Error> {
-        let len = self.keys.len();
-        let hash = key.hash();
-        let pos = hash % len;
+impl std::fmt::Debug for String<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
 
-        let mut index = pos;
+impl PartialEq for String<'_> {
+    fn eq(&self, other: &String<'_>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
 
-        for i in 0..len {
-            index = (i + pos) % len;
+impl PartialEq<str> for String<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
 
-            if self.keys.at(index)?.is_zero() {
-                break;
+impl Eq for String<'_> {}
+
+impl std::hash::Hash for String<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl std::borrow::Borrow<str> for String<'_> {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl std::io::Write for Array<'_, u8> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.copy(buf).map_err(|_| std::io::Error::from(std::io::ErrorKind::OutOfMemory))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, K: std::hash::Hash + Eq, V> HashMap<'a, K, V> {
+    pub fn new(capacity: usize, allocator: &'a alloc::Allocator<'a>) -> Result<HashMap<'a, K, V>, err::Error> {
+        let mut map = HashMap::<K, V> {
+            buckets: std::ptr::NonNull::dangling(),
+            occupied: std::ptr::NonNull::dangling(),
+            capacity: 0,
+            len: 0,
+            allocator,
+            state: hash::RandomState::new(),
+        };
+
+        map.resize(buckets_for(capacity))?;
+
+        Ok(map)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, err::Error> {
+        match self.entry(key)? {
+            Entry::Occupied(mut entry) => Ok(Some(entry.insert(value))),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                Ok(None)
             }
         }
+    }
 
-        if (index + 1) % len == pos {
-            Err(err::Error::OutOfBounds)
-        } else {
-            self.keys.insert(index, key)?;
-            self.values.insert(index, value)?;
+    pub fn entry(&mut self, key: K) -> Result<Entry<'_, 'a, K, V>, err::Error> {
+        let hash = self.hash(&key);
 
-            Ok(())
+        if let Some(index) = self.find(hash, &key) {
+            return Ok(Entry::Occupied(OccupiedEntry { map: self, index }));
         }
+
+        self.reserve(1)?;
+
+        Ok(Entry::Vacant(VacantEntry { map: self, hash, key }))
     }
 
-    pub fn get(&mut self, key: &K) -> Option<V> {
-        let len = self.keys.len();
+    pub fn get<Q: std::hash::Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V> where K: std::borrow::Borrow<Q> {
+        self.find(self.hash(key), key).map(|index| &self.bucket(index).value)
+    }
 
-        let hash = key.hash();
-        let pos = hash % len;
+    pub fn get_mut<Q: std::hash::Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<&mut V> where K: std::borrow::Borrow<Q> {
+        self.find(self.hash(key), key).map(|index| &mut self.bucket_mut(index).value)
+    }
+
+    pub fn contains_key<Q: std::hash::Hash + Eq + ?Sized>(&self, key: &Q) -> bool where K: std::borrow::Borrow<Q> {
+        self.find(self.hash(key), key).is_some()
+    }
 
-        for i in 0..len {
-            let index = (i + pos) % len;
-            let at = self.keys.at(index).unwrap();
+    pub fn remove<Q: std::hash::Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<V> where K: std::borrow::Borrow<Q> {
+        self.find(self.hash(key), key).map(|index| self.take(index).value)
+    }
+
+    pub fn reserve(&mut self, additional: usize) -> Result<(), err::Error> {
+        let needed = self.len.checked_add(additional).ok_or(err::Error::Allocation)?;
+
+        if needed * LOAD_DENOMINATOR < self.capacity * LOAD_NUMERATOR {
+            return Ok(());
+        }
 
-            if at.is_zero() {
+        self.resize(buckets_for(needed).max(self.capacity * 2))
+    }
+
+    fn hash<Q: std::hash::Hash + ?Sized>(&self, key: &Q) -> usize {
+        std::hash::BuildHasher::hash_one(&self.state, key) as usize
+    }
+
+    fn find<Q: Eq + ?Sized>(&self, hash: usize, key: &Q) -> Option<usize> where K: std::borrow::Borrow<Q> {
+        let mask = self.capacity - 1;
+        let mut index = hash & mask;
+
+        for distance in 0..self.capacity {
+            if !self.is_occupied(index) {
                 return None;
-            } if at.eq(&key) {
-                return Some(self.values.at(index).unwrap());
             }
+
+            let bucket = self.bucket(index);
+
+            if (index.wrapping_sub(bucket.hash) & mask) < distance {
+                return None;
+            }
+
+            if bucket.hash == hash && bucket.key.borrow() == key {
+                return Some(index);
+            }
+
+            index = (index + 1) & mask;
         }
 
         None
     }
 }
+
+impl<'a, K, V> HashMap<'a, K, V> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity * LOAD_NUMERATOR / LOAD_DENOMINATOR
+    }
+
+    pub fn iter(&self) -> Iter<'_, 'a, K, V> {
+        Iter { map: self, index: 0, remaining: self.len }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, 'a, K, V> {
+        let remaining = self.len;
+
+        IterMut { map: self, index: 0, remaining }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    pub fn clear(&mut self) {
+        let mut index = 0;
+
+        while let Some(next) = self.next_occupied(index) {
+            unsafe { (*self.buckets.as_ptr().add(next)).assume_init_drop() };
+            index = next + 1;
+        }
+
+        unsafe { self.occupied.as_ptr().write_bytes(0, words_for(self.capacity)) };
+        self.len = 0;
+    }
+
+    fn resize(&mut self, capacity: usize) -> Result<(), err::Error> {
+        let allocator = self.allocator;
+        let buckets = std::ptr::NonNull::new(allocator.alloc(capacity)?).ok_or(err::Error::Allocation)?;
+        let occupied = std::ptr::NonNull::new(allocator.alloc::<u64>(words_for(capacity))?).ok_or(err::Error::Allocation)?;
+
+        unsafe { occupied.as_ptr().write_bytes(0, words_for(capacity)) };
+
+        let old_buckets = std::mem::replace(&mut self.buckets, buckets);
+        let old_occupied = std::mem::replace(&mut self.occupied, occupied);
+        let old_capacity = std::mem::replace(&mut self.capacity, capacity);
+        self.len = 0;
+
+        for index in 0..old_capacity {
+            if unsafe { *old_occupied.as_ptr().add(index / 64) } & (1 << (index % 64)) != 0 {
+                self.place(unsafe { (*old_buckets.as_ptr().add(index)).assume_init_read() });
+            }
+        }
+
+        unsafe {
+            allocator.dealloc(old_occupied.as_ptr(), words_for(old_capacity));
+            allocator.dealloc(old_buckets.as_ptr(), old_capacity);
+        }
+
+        Ok(())
+    }
+
+    fn place(&mut self, mut bucket: Bucket<K, V>) -> usize {
+        let mask = self.capacity - 1;
+        let mut index = bucket.hash & mask;
+        let mut distance = 0;
+        let mut placed = None;
+
+        loop {
+            if !self.is_occupied(index) {
+                unsafe { (*self.buckets.as_ptr().add(index)).write(bucket) };
+                self.set_occupied(index, true);
+                self.len += 1;
+
+                return placed.unwrap_or(index);
+            }
+
+            let existing = self.bucket_mut(index);
+            let existing_distance = index.wrapping_sub(existing.hash) & mask;
+
+            if existing_distance < distance {
+                std::mem::swap(existing, &mut bucket);
+                placed.get_or_insert(index);
+                distance = existing_distance;
+            }
+
+            index = (index + 1) & mask;
+            distance += 1;
+        }
+    }
+
+    fn take(&mut self, mut index: usize) -> Bucket<K, V> {
+        let mask = self.capacity - 1;
+        let bucket = unsafe { (*self.buckets.as_ptr().add(index)).assume_init_read() };
+
+        loop {
+            let next = (index + 1) & mask;
+
+            if !self.is_occupied(next) || self.bucket(next).hash & mask == next {
+                break;
+            }
+
+            unsafe { self.buckets.as_ptr().add(index).copy_from_nonoverlapping(self.buckets.as_ptr().add(next), 1) };
+            index = next;
+        }
+
+        self.set_occupied(index, false);
+        self.len -= 1;
+
+        bucket
+    }
+
+    fn bucket(&self, index: usize) -> &Bucket<K, V> {
+        unsafe { (*self.buckets.as_ptr().add(index)).assume_init_ref() }
+    }
+
+    fn bucket_mut(&mut self, index: usize) -> &mut Bucket<K, V> {
+        unsafe { (*self.buckets.as_ptr().add(index)).assume_init_mut() }
+    }
+
+    fn is_occupied(&self, index: usize) -> bool {
+        unsafe { *self.occupied.as_ptr().add(index / 64) & (1 << (index % 64)) != 0 }
+    }
+
+    fn set_occupied(&mut self, index: usize, occupied: bool) {
+        let word = unsafe { &mut *self.occupied.as_ptr().add(index / 64) };
+
+        if occupied {
+            *word |= 1 << (index % 64);
+        } else {
+            *word &= !(1 << (index % 64));
+        }
+    }
+
+    fn next_occupied(&self, from: usize) -> Option<usize> {
+        let mut word = from / 64;
+
+        if from >= self.capacity {
+            return None;
+        }
+
+        let mut bits = unsafe { *self.occupied.as_ptr().add(word) } & (u64::MAX << (from % 64));
+
+        loop {
+            if bits != 0 {
+                return Some(word * 64 + bits.trailing_zeros() as usize);
+            }
+
+            word += 1;
+
+            if word >= words_for(self.capacity) {
+                return None;
+            }
+
+            bits = unsafe { *self.occupied.as_ptr().add(word) };
+        }
+    }
+}
+
+impl<K, V> Drop for HashMap<'_, K, V> {
+    fn drop(&mut self) {
+        if self.capacity > 0 {
+            self.clear();
+        }
+    }
+}
+
+impl<'m, 'a, K, V> IntoIterator for &'m HashMap<'a, K, V> {
+    type Item = (&'m K, &'m V);
+    type IntoIter = Iter<'m, 'a, K, V>;
+
+    fn into_iter(self) -> Iter<'m, 'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'m, 'a, K, V> IntoIterator for &'m mut HashMap<'a, K, V> {
+    type Item = (&'m K, &'m mut V);
+    type IntoIter = IterMut<'m, 'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'m, 'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<'m, K, V> Iterator for Iter<'m, '_, K, V> {
+    type Item = (&'m K, &'m V);
+
+    fn next(&mut self) -> Option<(&'m K, &'m V)> {
+        let index = self.map.next_occupied(self.index)?;
+        let bucket = self.map.bucket(index);
+
+        self.index = index + 1;
+        self.remaining -= 1;
+
+        Some((&bucket.key, &bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'m, K, V> Iterator for IterMut<'m, '_, K, V> {
+    type Item = (&'m K, &'m mut V);
+
+    fn next(&mut self) -> Option<(&'m K, &'m mut V)> {
+        let index = self.map.next_occupied(self.index)?;
+        let bucket = unsafe { (*self.map.buckets.as_ptr().add(index)).assume_init_mut() };
+
+        self.index = index + 1;
+        self.remaining -= 1;
+
+        Some((&bucket.key, &mut bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, '_, K, V> {}
+impl<K, V> ExactSizeIterator for IterMut<'_, '_, K, V> {}
+
+impl<'m, 'a, K, V> Entry<'m, 'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'m mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'m mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn and_modify(mut self, f: impl FnOnce(&mut V)) -> Entry<'m, 'a, K, V> {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+
+        self
+    }
+}
+
+impl<'m, K, V> OccupiedEntry<'m, '_, K, V> {
+    pub fn key(&self) -> &K {
+        &self.map.bucket(self.index).key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.map.bucket(self.index).value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.bucket_mut(self.index).value
+    }
+
+    pub fn into_mut(self) -> &'m mut V {
+        &mut self.map.bucket_mut(self.index).value
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.map.take(self.index).value
+    }
+}
+
+impl<'m, K, V> VacantEntry<'m, '_, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn insert(self, value: V) -> &'m mut V {
+        let index = self.map.place(Bucket { hash: self.hash, key: self.key, value });
+
+        &mut self.map.bucket_mut(index).value
+    }
+}
+
+fn buckets_for(len: usize) -> usize {
+    (len * LOAD_DENOMINATOR / LOAD_NUMERATOR + 1).next_power_of_two().max(8)
+}
+
+fn words_for(buckets: usize) -> usize {
+    buckets.div_ceil(64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn array_insert_and_remove_shift_elements() {
+        let allocator = alloc::Allocator::new(1 << 12);
+        let mut array = Array::new(4, &allocator).unwrap();
+        array.append_slice(&[1, 2, 3]).unwrap();
+
+        array.insert(0, 0).unwrap();
+        array.insert(2, 9).unwrap();
+        array.insert(5, 4).unwrap();
+        assert_eq!(array.slice(), [0, 1, 9, 2, 3, 4]);
+
+        assert_eq!(array.remove(2).unwrap(), 9);
+        assert_eq!(array.slice(), [0, 1, 2, 3, 4]);
+
+        assert_eq!(array.swap_remove(1).unwrap(), 1);
+        assert_eq!(array.slice(), [0, 4, 2, 3]);
+
+        assert_eq!(array.swap_remove(3).unwrap(), 3);
+        assert_eq!(array.pop(), Some(2));
+        assert_eq!(array.slice(), [0, 4]);
+    }
+
+    #[test]
+    fn array_rejects_out_of_bounds_indices() {
+        let allocator = alloc::Allocator::new(1 << 12);
+        let mut array = Array::new(2, &allocator).unwrap();
+        array.append_slice(&[1u32, 2]).unwrap();
+
+        assert!(matches!(array.insert(3, 0), Err(err::Error::OutOfBounds)));
+        assert!(matches!(array.remove(2), Err(err::Error::OutOfBounds)));
+        assert!(matches!(array.swap_remove(2), Err(err::Error::OutOfBounds)));
+        assert!(matches!(array.at(2), Err(err::Error::OutOfBounds)));
+        assert_eq!(array.slice(), [1, 2]);
+
+        array.clear();
+        assert_eq!(array.pop(), None);
+        assert!(matches!(array.remove(0), Err(err::Error::OutOfBounds)));
+    }
+
+    #[test]
+    fn array_keeps_contents_across_growth() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let mut array = Array::new(1, &allocator).unwrap();
+        let mut other = Array::new(1, &allocator).unwrap();
+
+        for value in 0..1000u64 {
+            array.push(value).unwrap();
+            other.push(value).unwrap();
+        }
+
+        assert!(array.cap() >= 1000);
+        assert!(array.slice().iter().copied().eq(0..1000));
+        assert!(array == other);
+    }
+
+    #[test]
+    fn array_try_extend_stops_at_the_arena_limit() {
+        let allocator = alloc::Allocator::new(256);
+        let mut array = Array::new(0, &allocator).unwrap();
+
+        array.try_extend(0..8u64).unwrap();
+        assert_eq!(array.slice(), [0, 1, 2, 3, 4, 5, 6, 7]);
+
+        assert!(matches!(array.try_extend(8..1000u64), Err(err::Error::Allocation)));
+        assert!(array.slice().iter().copied().eq(0..array.len() as u64));
+    }
+
+    #[test]
+    fn array_drops_each_element_once() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let value = std::rc::Rc::new(());
+        let count = || std::rc::Rc::strong_count(&value) - 1;
+
+        {
+            let mut array = Array::new(2, &allocator).unwrap();
+            array.try_extend(std::iter::repeat_n(value.clone(), 10)).unwrap();
+            assert_eq!(count(), 10);
+
+            drop(array.remove(0).unwrap());
+            drop(array.swap_remove(0).unwrap());
+            array.insert(3, value.clone()).unwrap();
+            assert_eq!(count(), 9);
+
+            array.truncate(6);
+            assert_eq!(count(), 6);
+
+            array.resize(8, value.clone()).unwrap();
+            assert_eq!(count(), 8);
+
+            array.resize(2, value.clone()).unwrap();
+            assert_eq!(count(), 2);
+        }
+
+        assert_eq!(count(), 0);
+
+        let mut array = Array::new(4, &allocator).unwrap();
+        array.try_extend(std::iter::repeat_n(value.clone(), 4)).unwrap();
+
+        let mut iter = array.into_iter();
+        drop(iter.next());
+        assert_eq!(count(), 3);
+
+        drop(iter);
+        assert_eq!(count(), 0);
+    }
+
+    #[test]
+    fn string_from_utf8_rejects_invalid_input() {
+        let allocator = alloc::Allocator::new(1 << 12);
+
+        for invalid in [&b"\xff"[..], b"ab\xc3", b"\xe2\x82", b"\xed\xa0\x80", b"\xc0\xaf"] {
+            let mut bytes = Array::new(4, &allocator).unwrap();
+            bytes.append_slice(invalid).unwrap();
+            assert!(matches!(String::from_utf8(bytes), Err(err::Error::Utf8)));
+        }
+
+        let mut bytes = Array::new(8, &allocator).unwrap();
+        bytes.append_slice("h\u{e9}llo".as_bytes()).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap().as_str(), "h\u{e9}llo");
+
+        let mut string = String::new(4, &allocator).unwrap();
+        assert!(matches!(string.push_bytes(b"a\xffb"), Err(err::Error::Utf8)));
+        assert!(string.is_empty());
+    }
+
+    #[test]
+    fn string_push_pop_and_truncate_respect_char_boundaries() {
+        let allocator = alloc::Allocator::new(1 << 12);
+        let mut string = String::new(1, &allocator).unwrap();
+
+        for c in ['a', '\u{e9}', '\u{20ac}', '\u{1f600}'] {
+            string.push(c).unwrap();
+        }
+
+        assert_eq!(string.len(), 1 + 2 + 3 + 4);
+        assert_eq!(string.as_str(), "a\u{e9}\u{20ac}\u{1f600}");
+
+        assert_eq!(string.pop(), Some('\u{1f600}'));
+        assert_eq!(string.len(), 6);
+
+        for len in [2, 4, 5] {
+            assert!(matches!(string.truncate(len), Err(err::Error::Utf8)));
+        }
+
+        assert_eq!(string.as_str(), "a\u{e9}\u{20ac}");
+
+        string.truncate(3).unwrap();
+        assert_eq!(string.as_str(), "a\u{e9}");
+
+        assert_eq!(string.pop(), Some('\u{e9}'));
+        assert_eq!(string.pop(), Some('a'));
+        assert_eq!(string.pop(), None);
+        assert!(string.is_empty());
+    }
+
+    #[test]
+    fn string_supports_write_macros() {
+        use std::fmt::Write;
+
+        let allocator = alloc::Allocator::new(1 << 12);
+        let mut string = String::from_str("x = ", &allocator).unwrap();
+
+        write!(string, "{} {:>4} {:?} \u{e9}", 42, "ab", "q").unwrap();
+        assert_eq!(string.as_str(), "x = 42   ab \"q\" \u{e9}");
+        assert_eq!(string.to_string(), string.as_str());
+
+        let limited = alloc::Allocator::new(64);
+        let mut string = String::new(0, &limited).unwrap();
+        assert!(write!(string, "{}", "y".repeat(128)).is_err());
+    }
+
+    fn assert_probe_chains<K, V>(map: &HashMap<K, V>) {
+        let mask = map.capacity - 1;
+
+        for index in 0..map.capacity {
+            if map.is_occupied(index) {
+                let home = map.bucket(index).hash & mask;
+                let mut slot = home;
+
+                while slot != index {
+                    assert!(map.is_occupied(slot), "gap at {} in the chain from {} to {}", slot, home, index);
+                    slot = (slot + 1) & mask;
+                }
+            }
+        }
+    }
+
+    fn keys_with_home(map: &HashMap<u64, u64>, home: usize, count: usize) -> Vec<u64> {
+        (0..).filter(|key| map.hash(key) & (map.capacity - 1) == home).take(count).collect()
+    }
+
+    #[test]
+    fn insert_get_and_overwrite() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let mut map = HashMap::new(4, &allocator).unwrap();
+
+        assert_eq!(map.insert(1u64, 10u64).unwrap(), None);
+        assert_eq!(map.insert(2, 20).unwrap(), None);
+        assert_eq!(map.insert(1, 11).unwrap(), Some(10));
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&1), Some(&11));
+        assert_eq!(map.get(&2), Some(&20));
+        assert_eq!(map.get(&3), None);
+    }
+
+    #[test]
+    fn remove_leaves_no_tombstones() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let mut map = HashMap::new(64, &allocator).unwrap();
+
+        for key in 0..56u64 {
+            map.insert(key, key * 2).unwrap();
+        }
+
+        for key in (0..56u64).step_by(2) {
+            assert_eq!(map.remove(&key), Some(key * 2));
+            assert_probe_chains(&map);
+        }
+
+        assert_eq!(map.len(), 28);
+        assert_eq!(map.remove(&0), None);
+
+        for key in 0..56u64 {
+            assert_eq!(map.get(&key).copied(), (key % 2 == 1).then_some(key * 2));
+        }
+
+        for key in (1..56u64).step_by(2) {
+            map.remove(&key);
+        }
+
+        assert!(map.is_empty());
+        assert!((0..map.capacity).all(|index| !map.is_occupied(index)));
+    }
+
+    #[test]
+    fn probe_chains_wrap_around_the_table() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let mut map = HashMap::new(4, &allocator).unwrap();
+        let last = map.capacity - 1;
+        let keys = keys_with_home(&map, last, 3);
+
+        for &key in &keys {
+            map.insert(key, key).unwrap();
+        }
+
+        assert!(map.is_occupied(last) && map.is_occupied(0) && map.is_occupied(1));
+        assert_probe_chains(&map);
+
+        assert_eq!(map.remove(&keys[0]), Some(keys[0]));
+        assert!(map.is_occupied(last) && map.is_occupied(0) && !map.is_occupied(1));
+        assert_probe_chains(&map);
+
+        assert_eq!(map.get(&keys[1]), Some(&keys[1]));
+        assert_eq!(map.get(&keys[2]), Some(&keys[2]));
+    }
+
+    #[test]
+    fn grows_past_the_initial_capacity() {
+        let allocator = alloc::Allocator::new(1 << 20);
+        let mut map = HashMap::new(1, &allocator).unwrap();
+        let initial = map.capacity();
+
+        for key in 0..1000u64 {
+            map.insert(key, key + 1).unwrap();
+        }
+
+        assert!(map.capacity() >= 1000 && map.capacity() > initial);
+        assert_eq!(map.len(), 1000);
+        assert!((0..1000u64).all(|key| map.get(&key) == Some(&(key + 1))));
+        assert_probe_chains(&map);
+    }
+
+    #[test]
+    fn iterates_every_entry_once() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let mut map = HashMap::new(16, &allocator).unwrap();
+
+        for key in 0..100u64 {
+            map.insert(key, key).unwrap();
+        }
+
+        for (_, value) in map.iter_mut() {
+            *value *= 3;
+        }
+
+        let mut entries: Vec<(u64, u64)> = map.iter().map(|(k, v)| (*k, *v)).collect();
+        entries.sort();
+
+        assert_eq!(map.iter().len(), 100);
+        assert_eq!(entries, (0..100u64).map(|k| (k, k * 3)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drops_each_value_once() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let value = std::rc::Rc::new(());
+
+        {
+            let mut map = HashMap::new(8, &allocator).unwrap();
+
+            for key in 0..40u64 {
+                map.insert(key, value.clone()).unwrap();
+            }
+
+            for key in 0..20u64 {
+                map.remove(&key);
+            }
+
+            assert_eq!(std::rc::Rc::strong_count(&value), 21);
+        }
+
+        assert_eq!(std::rc::Rc::strong_count(&value), 1);
+    }
+}
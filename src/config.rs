@@ -0,0 +1,576 @@
+use crate::err;
+
+const DEFAULT_PATH: &str = "web.toml";
+const UNIX_PREFIX: &str = "unix:";
+
+const KEYS: [&str; 20] = [
+    "addresses", "port", "ipv6", "max_connections", "overload", "retry_after",
+    "arena.size", "arena.context_size", "arena.request_size", "arena.request_limit", "arena.table_size",
+    "timeout.header", "timeout.body", "timeout.write", "timeout.idle", "timeout.shutdown",
+    "tls.addresses", "tls.alpn", "tls.cert", "tls.key",
+];
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub addresses: Vec<String>,
+    pub port: Option<u16>,
+    pub ipv6: bool,
+    pub arena_size: usize,
+    pub context_arena_size: usize,
+    pub request_arena_size: usize,
+    pub request_arena_limit: usize,
+    pub table_size: usize,
+    pub max_connections: usize,
+    pub overload: Overload,
+    pub retry_after: u32,
+    pub header_timeout: std::time::Duration,
+    pub body_timeout: std::time::Duration,
+    pub write_timeout: std::time::Duration,
+    pub idle_timeout: std::time::Duration,
+    pub shutdown_timeout: std::time::Duration,
+    pub tls_addresses: Vec<String>,
+    pub tls_alpn: Vec<String>,
+    pub tls_certificates: Vec<TlsCertificate>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overload {
+    Reject,
+    Pause,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TlsCertificate {
+    pub names: Vec<String>,
+    pub cert: std::path::PathBuf,
+    pub key: std::path::PathBuf,
+}
+
+enum Value {
+    Text(String),
+    List(Vec<String>),
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            addresses: vec!["127.0.0.1:8080".to_owned()],
+            port: None,
+            ipv6: false,
+            arena_size: 128 * 4096,
+            context_arena_size: 4 * 4096,
+            request_arena_size: 4 * 4096,
+            request_arena_limit: 4 << 20,
+            table_size: 20,
+            max_connections: 20,
+            overload: Overload::Reject,
+            retry_after: 1,
+            header_timeout: std::time::Duration::from_secs(10),
+            body_timeout: std::time::Duration::from_secs(30),
+            write_timeout: std::time::Duration::from_secs(30),
+            idle_timeout: std::time::Duration::from_secs(60),
+            shutdown_timeout: std::time::Duration::from_secs(30),
+            tls_addresses: Vec::new(),
+            tls_alpn: vec!["h2".to_owned(), "http/1.1".to_owned()],
+            tls_certificates: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load() -> Result<ServerConfig, err::Error> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let vars: Vec<(String, String)> = std::env::vars().collect();
+
+        ServerConfig::from_sources(&args, &vars)
+    }
+
+    pub fn from_sources(args: &[String], vars: &[(String, String)]) -> Result<ServerConfig, err::Error> {
+        let mut config = ServerConfig::default();
+
+        let path = flag_value(args, "--config").or_else(|| vars.iter().find(|(name, _)| name == "WEB_CONFIG").map(|(_, value)| value.clone()));
+
+        match path {
+            Some(path) => config.read_file(&path)?,
+            None if std::path::Path::new(DEFAULT_PATH).is_file() => config.read_file(DEFAULT_PATH)?,
+            None => {}
+        }
+
+        config.read_vars(vars)?;
+        config.read_args(args)?;
+
+        Ok(config)
+    }
+
+    pub fn read_file(&mut self, path: &str) -> Result<(), err::Error> {
+        let source = std::fs::read_to_string(path).map_err(|_| err::Error::FileNotFound)?;
+        self.read_toml(&source)
+    }
+
+    pub fn read_toml(&mut self, source: &str) -> Result<(), err::Error> {
+        let mut section = String::new();
+
+        for line in source.lines() {
+            let line = strip_comment(line).trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+                section = name.trim().to_owned();
+
+                match section.as_str() {
+                    "tls.certificate" => self.tls_certificates.push(TlsCertificate::default()),
+                    _ => return Err(err::Error::Config),
+                }
+
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_owned();
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or(err::Error::Config)?;
+            let key = key.trim();
+
+            let key = if section.is_empty() { key.to_owned() } else { format!("{}.{}", section, key) };
+            self.set(&key, parse_value(value.trim())?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_env(&mut self) -> Result<(), err::Error> {
+        let vars: Vec<(String, String)> = std::env::vars().collect();
+        self.read_vars(&vars)
+    }
+
+    pub fn read_vars(&mut self, vars: &[(String, String)]) -> Result<(), err::Error> {
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix("WEB_") else {
+                continue;
+            };
+
+            let key = section_key(&key.to_ascii_lowercase());
+
+            if !KEYS.contains(&key.as_str()) {
+                continue;
+            }
+
+            let value = if key == "addresses" || key == "tls.addresses" || key == "tls.alpn" { Value::List(split_list(value)) } else { Value::Text(value.clone()) };
+
+            self.set(&key, value)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_args(&mut self, args: &[String]) -> Result<(), err::Error> {
+        let mut addresses = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                return Err(err::Error::Config);
+            };
+
+            let (flag, inline) = match flag.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_owned())),
+                None => (flag, None),
+            };
+
+            if flag == "ipv6" && inline.is_none() {
+                self.ipv6 = true;
+                continue;
+            }
+
+            let value = match inline {
+                Some(value) => value,
+                None => iter.next().ok_or(err::Error::Config)?.clone(),
+            };
+
+            match flag {
+                "config" => {}
+                "address" => addresses.push(value),
+                _ => {
+                    let key = section_key(&flag.replace('-', "_"));
+                    self.set(&key, Value::Text(value))?;
+                }
+            }
+        }
+
+        if !addresses.is_empty() {
+            self.addresses = addresses;
+        }
+
+        Ok(())
+    }
+
+    pub fn socket_addrs(&self) -> Result<Vec<std::net::SocketAddr>, err::Error> {
+        let mut addrs = Vec::new();
+
+        for address in self.addresses.iter().filter(|a| !a.starts_with(UNIX_PREFIX)) {
+            let resolved = std::net::ToSocketAddrs::to_socket_addrs(address.as_str()).map_err(|_| err::Error::Config)?;
+
+            for mut addr in resolved {
+                if addr.is_ipv6() && !self.ipv6 {
+                    continue;
+                }
+
+                if let Some(port) = self.port {
+                    addr.set_port(port);
+                }
+
+                if !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
+            }
+        }
+
+        if addrs.is_empty() && self.unix_paths().is_empty() {
+            Err(err::Error::Config)
+        } else {
+            Ok(addrs)
+        }
+    }
+
+    pub fn tls_socket_addrs(&self) -> Result<Vec<std::net::SocketAddr>, err::Error> {
+        let mut addrs = Vec::new();
+
+        for address in &self.tls_addresses {
+            for addr in std::net::ToSocketAddrs::to_socket_addrs(address.as_str()).map_err(|_| err::Error::Config)? {
+                if (self.ipv6 || addr.is_ipv4()) && !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
+            }
+        }
+
+        Ok(addrs)
+    }
+
+    pub fn unix_paths(&self) -> Vec<std::path::PathBuf> {
+        self.addresses.iter().filter_map(|a| a.strip_prefix(UNIX_PREFIX)).map(std::path::PathBuf::from).collect()
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), err::Error> {
+        match key {
+            "addresses" => self.addresses = value.list()?,
+            "port" => self.port = Some(value.number()?),
+            "ipv6" => self.ipv6 = value.boolean()?,
+            "max_connections" => self.max_connections = value.number()?,
+            "overload" => self.overload = match value.text()? {
+                "reject" => Overload::Reject,
+                "pause" => Overload::Pause,
+                _ => return Err(err::Error::Config),
+            },
+            "retry_after" => self.retry_after = value.number()?,
+            "arena.size" => self.arena_size = value.number()?,
+            "arena.context_size" => self.context_arena_size = value.number()?,
+            "arena.request_size" => self.request_arena_size = value.number()?,
+            "arena.request_limit" => self.request_arena_limit = value.number()?,
+            "arena.table_size" => self.table_size = value.number()?,
+            "timeout.header" => self.header_timeout = value.seconds()?,
+            "timeout.body" => self.body_timeout = value.seconds()?,
+            "timeout.write" => self.write_timeout = value.seconds()?,
+            "timeout.idle" => self.idle_timeout = value.seconds()?,
+            "timeout.shutdown" => self.shutdown_timeout = value.seconds()?,
+            "tls.addresses" => self.tls_addresses = value.list()?,
+            "tls.alpn" => self.tls_alpn = value.list()?,
+            "tls.cert" => self.default_certificate().cert = value.text()?.into(),
+            "tls.key" => self.default_certificate().key = value.text()?.into(),
+            "tls.certificate.names" => self.last_certificate()?.names = value.list()?,
+            "tls.certificate.cert" => self.last_certificate()?.cert = value.text()?.into(),
+            "tls.certificate.key" => self.last_certificate()?.key = value.text()?.into(),
+            _ => return Err(err::Error::Config),
+        }
+
+        Ok(())
+    }
+
+    fn default_certificate(&mut self) -> &mut TlsCertificate {
+        if !self.tls_certificates.first().is_some_and(|c| c.names.is_empty()) {
+            self.tls_certificates.insert(0, TlsCertificate::default());
+        }
+
+        &mut self.tls_certificates[0]
+    }
+
+    fn last_certificate(&mut self) -> Result<&mut TlsCertificate, err::Error> {
+        self.tls_certificates.last_mut().ok_or(err::Error::Config)
+    }
+}
+
+impl Value {
+    fn text(&self) -> Result<&str, err::Error> {
+        match self {
+            Value::Text(text) => Ok(text),
+            Value::List(_) => Err(err::Error::Config),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&self) -> Result<T, err::Error> {
+        self.text()?.replace('_', "").parse().map_err(|_| err::Error::Config)
+    }
+
+    fn boolean(&self) -> Result<bool, err::Error> {
+        match self.text()? {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(err::Error::Config),
+        }
+    }
+
+    fn seconds(&self) -> Result<std::time::Duration, err::Error> {
+        Ok(std::time::Duration::from_secs(self.number()?))
+    }
+
+    fn list(self) -> Result<Vec<String>, err::Error> {
+        match self {
+            Value::List(list) => Ok(list),
+            Value::Text(text) => Ok(vec![text]),
+        }
+    }
+}
+
+fn parse_value(value: &str) -> Result<Value, err::Error> {
+    if let Some(inner) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        let mut list = Vec::new();
+        let mut start = 0;
+        let mut quoted = false;
+        let mut escaped = false;
+
+        for (i, c) in inner.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    push_item(&mut list, &inner[start..i])?;
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+
+        if quoted {
+            return Err(err::Error::Config);
+        }
+
+        push_item(&mut list, &inner[start..])?;
+
+        Ok(Value::List(list))
+    } else if value.starts_with('"') {
+        Ok(Value::Text(unquote(value)?))
+    } else {
+        Ok(Value::Text(value.to_owned()))
+    }
+}
+
+fn push_item(list: &mut Vec<String>, item: &str) -> Result<(), err::Error> {
+    let item = item.trim();
+
+    if !item.is_empty() {
+        list.push(unquote(item)?);
+    }
+
+    Ok(())
+}
+
+fn unquote(value: &str) -> Result<String, err::Error> {
+    let inner = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).ok_or(err::Error::Config)?;
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push(match chars.next() {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                _ => return Err(err::Error::Config),
+            }),
+            '"' => return Err(err::Error::Config),
+            c => text.push(c),
+        }
+    }
+
+    Ok(text)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn section_key(key: &str) -> String {
+    for section in ["arena", "timeout", "tls"] {
+        if let Some(rest) = key.strip_prefix(section).and_then(|k| k.strip_prefix('_')) {
+            return format!("{}.{}", section, rest);
+        }
+    }
+
+    key.to_owned()
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',').map(|v| v.trim().to_owned()).filter(|v| !v.is_empty()).collect()
+}
+
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        if arg == flag {
+            return iter.next().cloned();
+        }
+
+        if let Some(value) = arg.strip_prefix(flag).and_then(|a| a.strip_prefix('=')) {
+            return Some(value.to_owned());
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn vars(values: &[(&str, &str)]) -> Vec<(String, String)> {
+        values.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn reads_toml_sections_lists_and_comments() {
+        let mut config = ServerConfig::default();
+        config.read_toml(r#"
+            # top level
+            addresses = ["127.0.0.1:9000", "unix:/tmp/web.sock"] # trailing comment
+            max_connections = 1_000
+            overload = "pause"
+
+            [arena]
+            request_limit = 65536
+
+            [timeout]
+            idle = 5
+
+            [tls]
+            alpn = ["h2"]
+
+            [[tls.certificate]]
+            names = ["a.test", "b.test"]
+            cert = "a.pem"
+            key = "a#key.pem"
+        "#).unwrap();
+
+        assert_eq!(config.addresses, ["127.0.0.1:9000", "unix:/tmp/web.sock"]);
+        assert_eq!(config.max_connections, 1000);
+        assert_eq!(config.overload, Overload::Pause);
+        assert_eq!(config.request_arena_limit, 65536);
+        assert_eq!(config.idle_timeout, std::time::Duration::from_secs(5));
+        assert_eq!(config.tls_alpn, ["h2"]);
+        assert_eq!(config.tls_certificates.len(), 1);
+        assert_eq!(config.tls_certificates[0].names, ["a.test", "b.test"]);
+        assert_eq!(config.tls_certificates[0].key, std::path::PathBuf::from("a#key.pem"));
+    }
+
+    #[test]
+    fn keeps_commas_and_escapes_inside_quoted_items() {
+        let Value::List(list) = parse_value(r#"["a,b", "c\"d,e", "f\\", " g "]"#).unwrap() else {
+            panic!("expected a list");
+        };
+
+        assert_eq!(list, ["a,b", "c\"d,e", "f\\", " g "]);
+        assert_eq!(unquote(r#""tab\there\nnext""#).unwrap(), "tab\there\nnext");
+        assert_eq!(strip_comment(r#"key = "a \" # b" # comment"#), r#"key = "a \" # b" "#);
+    }
+
+    #[test]
+    fn rejects_malformed_toml() {
+        for source in [
+            "addresses = [\"a\", \"b]",
+            "addresses = [unquoted]",
+            "key = \"bad \\x escape\"",
+            "key = \"stray \" quote\"",
+            "max_connections = many",
+            "unknown = 1",
+            "no equals sign",
+            "[[unknown]]",
+        ] {
+            assert!(ServerConfig::default().read_toml(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn reads_prefixed_environment_variables() {
+        let mut config = ServerConfig::default();
+        config.read_vars(&vars(&[
+            ("WEB_PORT", "9001"),
+            ("WEB_ARENA_CONTEXT_SIZE", "32768"),
+            ("WEB_TIMEOUT_HEADER", "3"),
+            ("WEB_ADDRESSES", "127.0.0.1:1, 127.0.0.1:2"),
+            ("WEB_UNRELATED", "ignored"),
+            ("PORT", "1"),
+        ])).unwrap();
+
+        assert_eq!(config.port, Some(9001));
+        assert_eq!(config.context_arena_size, 32768);
+        assert_eq!(config.header_timeout, std::time::Duration::from_secs(3));
+        assert_eq!(config.addresses, ["127.0.0.1:1", "127.0.0.1:2"]);
+    }
+
+    #[test]
+    fn reads_command_line_flags() {
+        let mut config = ServerConfig::default();
+        config.read_args(&args(&["--port", "9002", "--arena-request-size=8192", "--ipv6", "--address", "127.0.0.1:1", "--address=127.0.0.1:2", "--overload", "pause"])).unwrap();
+
+        assert_eq!(config.port, Some(9002));
+        assert_eq!(config.request_arena_size, 8192);
+        assert!(config.ipv6);
+        assert_eq!(config.addresses, ["127.0.0.1:1", "127.0.0.1:2"]);
+        assert_eq!(config.overload, Overload::Pause);
+
+        assert!(ServerConfig::default().read_args(&args(&["--port"])).is_err());
+        assert!(ServerConfig::default().read_args(&args(&["port", "1"])).is_err());
+        assert!(ServerConfig::default().read_args(&args(&["--unknown", "1"])).is_err());
+    }
+
+    #[test]
+    fn flags_override_environment_override_file() {
+        let path = std::env::temp_dir().join(format!("web-config-{}.toml", std::process::id()));
+        std::fs::write(&path, "port = 1\nretry_after = 2\nmax_connections = 3\n").unwrap();
+
+        let config = ServerConfig::from_sources(
+            &args(&["--config", path.to_str().unwrap(), "--port", "30"]),
+            &vars(&[("WEB_PORT", "10"), ("WEB_RETRY_AFTER", "20")]),
+        );
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(config.port, Some(30));
+        assert_eq!(config.retry_after, 20);
+        assert_eq!(config.max_connections, 3);
+    }
+}
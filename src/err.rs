@@ -8,4 +8,9 @@ pub enum Error {
     HttpMethod,
     Protocol,
     HttpVersion,
+    Template,
+    Config,
+    Signal,
+    Tls,
+    Utf8,
 }
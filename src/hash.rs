@@ -0,0 +1,203 @@
+static SEED: std::sync::OnceLock<(u64, u64)> = std::sync::OnceLock::new();
+
+#[derive(Debug, Clone, Copy)]
+pub struct RandomState {
+    k0: u64,
+    k1: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SipHasher {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    tail: u64,
+    tail_len: usize,
+    length: usize,
+}
+
+impl RandomState {
+    pub fn new() -> RandomState {
+        let (k0, k1) = *SEED.get_or_init(seed);
+
+        RandomState { k0, k1 }
+    }
+
+    pub const fn with_keys(k0: u64, k1: u64) -> RandomState {
+        RandomState { k0, k1 }
+    }
+}
+
+impl Default for RandomState {
+    fn default() -> RandomState {
+        RandomState::new()
+    }
+}
+
+impl std::hash::BuildHasher for RandomState {
+    type Hasher = SipHasher;
+
+    fn build_hasher(&self) -> SipHasher {
+        SipHasher::new(self.k0, self.k1)
+    }
+}
+
+impl SipHasher {
+    pub const fn new(k0: u64, k1: u64) -> SipHasher {
+        SipHasher {
+            v0: k0 ^ 0x736f6d6570736575,
+            v1: k1 ^ 0x646f72616e646f6d,
+            v2: k0 ^ 0x6c7967656e657261,
+            v3: k1 ^ 0x7465646279746573,
+            tail: 0,
+            tail_len: 0,
+            length: 0,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13) ^ self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16) ^ self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21) ^ self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17) ^ self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    fn compress(&mut self, word: u64) {
+        self.v3 ^= word;
+        self.round();
+        self.v0 ^= word;
+    }
+}
+
+impl std::hash::Hasher for SipHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.length += bytes.len();
+
+        let mut bytes = bytes;
+
+        if self.tail_len > 0 {
+            let take = (8 - self.tail_len).min(bytes.len());
+
+            for (i, byte) in bytes[..take].iter().enumerate() {
+                self.tail |= (*byte as u64) << (8 * (self.tail_len + i));
+            }
+
+            self.tail_len += take;
+            bytes = &bytes[take..];
+
+            if self.tail_len < 8 {
+                return;
+            }
+
+            self.compress(self.tail);
+            self.tail = 0;
+            self.tail_len = 0;
+        }
+
+        let mut words = bytes.chunks_exact(8);
+
+        for word in &mut words {
+            self.compress(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+
+        for (i, byte) in words.remainder().iter().enumerate() {
+            self.tail |= (*byte as u64) << (8 * i);
+        }
+
+        self.tail_len = words.remainder().len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+        let last = ((self.length as u64 & 0xff) << 56) | self.tail;
+
+        state.compress(last);
+        state.v2 ^= 0xff;
+
+        for _ in 0..3 {
+            state.round();
+        }
+
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+fn seed() -> (u64, u64) {
+    let mut bytes = [0u8; 16];
+    let read = unsafe { libc::getrandom(bytes.as_mut_ptr() as *mut libc::c_void, bytes.len(), 0) };
+
+    if read != bytes.len() as isize {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+        let stack = &bytes as *const _ as u64;
+
+        return (nanos ^ stack, (std::process::id() as u64).rotate_left(32) ^ nanos.rotate_left(17));
+    }
+
+    (u64::from_le_bytes(bytes[..8].try_into().unwrap()), u64::from_le_bytes(bytes[8..].try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::{BuildHasher, Hash, Hasher};
+
+    fn reference(bytes: &[u8]) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    #[test]
+    fn matches_sip_1_3_for_every_tail_length() {
+        let input: Vec<u8> = (0..64).collect();
+
+        for length in 0..=input.len() {
+            let mut hasher = SipHasher::new(0, 0);
+            hasher.write(&input[..length]);
+
+            assert_eq!(hasher.finish(), reference(&input[..length]), "length {}", length);
+        }
+    }
+
+    #[test]
+    fn split_writes_match_a_single_write() {
+        let input: Vec<u8> = (0..40).collect();
+
+        for split in 0..=input.len() {
+            let mut hasher = SipHasher::new(0, 0);
+            hasher.write(&input[..split]);
+            hasher.write(&input[split..]);
+
+            assert_eq!(hasher.finish(), reference(&input), "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn hashes_values_like_the_standard_hasher() {
+        let state = RandomState::with_keys(0, 0);
+
+        for value in ["", "a", "/hello", "/assets/style.css"] {
+            let mut expected = std::collections::hash_map::DefaultHasher::new();
+            value.hash(&mut expected);
+
+            assert_eq!(state.hash_one(value), expected.finish());
+        }
+
+        let mut expected = std::collections::hash_map::DefaultHasher::new();
+        (42u64, 7u32).hash(&mut expected);
+
+        assert_eq!(state.hash_one((42u64, 7u32)), expected.finish());
+    }
+
+    #[test]
+    fn keys_change_the_hash() {
+        assert_ne!(RandomState::with_keys(1, 2).hash_one(b"key"), RandomState::with_keys(0, 0).hash_one(b"key"));
+    }
+}
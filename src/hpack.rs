@@ -0,0 +1,551 @@
+use crate::err;
+
+const ENTRY_OVERHEAD: usize = 32;
+
+const STATIC_TABLE: [(&[u8], &[u8]); 61] = [
+    (b":authority", b""),
+    (b":method", b"GET"),
+    (b":method", b"POST"),
+    (b":path", b"/"),
+    (b":path", b"/index.html"),
+    (b":scheme", b"http"),
+    (b":scheme", b"https"),
+    (b":status", b"200"),
+    (b":status", b"204"),
+    (b":status", b"206"),
+    (b":status", b"304"),
+    (b":status", b"400"),
+    (b":status", b"404"),
+    (b":status", b"500"),
+    (b"accept-charset", b""),
+    (b"accept-encoding", b"gzip, deflate"),
+    (b"accept-language", b""),
+    (b"accept-ranges", b""),
+    (b"accept", b""),
+    (b"access-control-allow-origin", b""),
+    (b"age", b""),
+    (b"allow", b""),
+    (b"authorization", b""),
+    (b"cache-control", b""),
+    (b"content-disposition", b""),
+    (b"content-encoding", b""),
+    (b"content-language", b""),
+    (b"content-length", b""),
+    (b"content-location", b""),
+    (b"content-range", b""),
+    (b"content-type", b""),
+    (b"cookie", b""),
+    (b"date", b""),
+    (b"etag", b""),
+    (b"expect", b""),
+    (b"expires", b""),
+    (b"from", b""),
+    (b"host", b""),
+    (b"if-match", b""),
+    (b"if-modified-since", b""),
+    (b"if-none-match", b""),
+    (b"if-range", b""),
+    (b"if-unmodified-since", b""),
+    (b"last-modified", b""),
+    (b"link", b""),
+    (b"location", b""),
+    (b"max-forwards", b""),
+    (b"proxy-authenticate", b""),
+    (b"proxy-authorization", b""),
+    (b"range", b""),
+    (b"referer", b""),
+    (b"refresh", b""),
+    (b"retry-after", b""),
+    (b"server", b""),
+    (b"set-cookie", b""),
+    (b"strict-transport-security", b""),
+    (b"transfer-encoding", b""),
+    (b"user-agent", b""),
+    (b"vary", b""),
+    (b"via", b""),
+    (b"www-authenticate", b""),
+];
+
+const HUFFMAN: [(u8, u32); 257] = [
+    (13, 0x1ff8), (23, 0x7fffd8), (28, 0xfffffe2), (28, 0xfffffe3), (28, 0xfffffe4), (28, 0xfffffe5),
+    (28, 0xfffffe6), (28, 0xfffffe7), (28, 0xfffffe8), (24, 0xffffea), (30, 0x3ffffffc), (28, 0xfffffe9),
+    (28, 0xfffffea), (30, 0x3ffffffd), (28, 0xfffffeb), (28, 0xfffffec), (28, 0xfffffed), (28, 0xfffffee),
+    (28, 0xfffffef), (28, 0xffffff0), (28, 0xffffff1), (28, 0xffffff2), (30, 0x3ffffffe), (28, 0xffffff3),
+    (28, 0xffffff4), (28, 0xffffff5), (28, 0xffffff6), (28, 0xffffff7), (28, 0xffffff8), (28, 0xffffff9),
+    (28, 0xffffffa), (28, 0xffffffb), (6, 0x14), (10, 0x3f8), (10, 0x3f9), (12, 0xffa),
+    (13, 0x1ff9), (6, 0x15), (8, 0xf8), (11, 0x7fa), (10, 0x3fa), (10, 0x3fb),
+    (8, 0xf9), (11, 0x7fb), (8, 0xfa), (6, 0x16), (6, 0x17), (6, 0x18),
+    (5, 0x0), (5, 0x1), (5, 0x2), (6, 0x19), (6, 0x1a), (6, 0x1b),
+    (6, 0x1c), (6, 0x1d), (6, 0x1e), (6, 0x1f), (7, 0x5c), (8, 0xfb),
+    (15, 0x7ffc), (6, 0x20), (12, 0xffb), (10, 0x3fc), (13, 0x1ffa), (6, 0x21),
+    (7, 0x5d), (7, 0x5e), (7, 0x5f), (7, 0x60), (7, 0x61), (7, 0x62),
+    (7, 0x63), (7, 0x64), (7, 0x65), (7, 0x66), (7, 0x67), (7, 0x68),
+    (7, 0x69), (7, 0x6a), (7, 0x6b), (7, 0x6c), (7, 0x6d), (7, 0x6e),
+    (7, 0x6f), (7, 0x70), (7, 0x71), (7, 0x72), (8, 0xfc), (7, 0x73),
+    (8, 0xfd), (13, 0x1ffb), (19, 0x7fff0), (13, 0x1ffc), (14, 0x3ffc), (6, 0x22),
+    (15, 0x7ffd), (5, 0x3), (6, 0x23), (5, 0x4), (6, 0x24), (5, 0x5),
+    (6, 0x25), (6, 0x26), (6, 0x27), (5, 0x6), (7, 0x74), (7, 0x75),
+    (6, 0x28), (6, 0x29), (6, 0x2a), (5, 0x7), (6, 0x2b), (7, 0x76),
+    (6, 0x2c), (5, 0x8), (5, 0x9), (6, 0x2d), (7, 0x77), (7, 0x78),
+    (7, 0x79), (7, 0x7a), (7, 0x7b), (15, 0x7ffe), (11, 0x7fc), (14, 0x3ffd),
+    (13, 0x1ffd), (28, 0xffffffc), (20, 0xfffe6), (22, 0x3fffd2), (20, 0xfffe7), (20, 0xfffe8),
+    (22, 0x3fffd3), (22, 0x3fffd4), (22, 0x3fffd5), (23, 0x7fffd9), (22, 0x3fffd6), (23, 0x7fffda),
+    (23, 0x7fffdb), (23, 0x7fffdc), (23, 0x7fffdd), (23, 0x7fffde), (24, 0xffffeb), (23, 0x7fffdf),
+    (24, 0xffffec), (24, 0xffffed), (22, 0x3fffd7), (23, 0x7fffe0), (24, 0xffffee), (23, 0x7fffe1),
+    (23, 0x7fffe2), (23, 0x7fffe3), (23, 0x7fffe4), (21, 0x1fffdc), (22, 0x3fffd8), (23, 0x7fffe5),
+    (22, 0x3fffd9), (23, 0x7fffe6), (23, 0x7fffe7), (24, 0xffffef), (22, 0x3fffda), (21, 0x1fffdd),
+    (20, 0xfffe9), (22, 0x3fffdb), (22, 0x3fffdc), (23, 0x7fffe8), (23, 0x7fffe9), (21, 0x1fffde),
+    (23, 0x7fffea), (22, 0x3fffdd), (22, 0x3fffde), (24, 0xfffff0), (21, 0x1fffdf), (22, 0x3fffdf),
+    (23, 0x7fffeb), (23, 0x7fffec), (21, 0x1fffe0), (21, 0x1fffe1), (22, 0x3fffe0), (21, 0x1fffe2),
+    (23, 0x7fffed), (22, 0x3fffe1), (23, 0x7fffee), (23, 0x7fffef), (20, 0xfffea), (22, 0x3fffe2),
+    (22, 0x3fffe3), (22, 0x3fffe4), (23, 0x7ffff0), (22, 0x3fffe5), (22, 0x3fffe6), (23, 0x7ffff1),
+    (26, 0x3ffffe0), (26, 0x3ffffe1), (20, 0xfffeb), (19, 0x7fff1), (22, 0x3fffe7), (23, 0x7ffff2),
+    (22, 0x3fffe8), (25, 0x1ffffec), (26, 0x3ffffe2), (26, 0x3ffffe3), (26, 0x3ffffe4), (27, 0x7ffffde),
+    (27, 0x7ffffdf), (26, 0x3ffffe5), (24, 0xfffff1), (25, 0x1ffffed), (19, 0x7fff2), (21, 0x1fffe3),
+    (26, 0x3ffffe6), (27, 0x7ffffe0), (27, 0x7ffffe1), (26, 0x3ffffe7), (27, 0x7ffffe2), (24, 0xfffff2),
+    (21, 0x1fffe4), (21, 0x1fffe5), (26, 0x3ffffe8), (26, 0x3ffffe9), (28, 0xffffffd), (27, 0x7ffffe3),
+    (27, 0x7ffffe4), (27, 0x7ffffe5), (20, 0xfffec), (24, 0xfffff3), (20, 0xfffed), (21, 0x1fffe6),
+    (22, 0x3fffe9), (21, 0x1fffe7), (21, 0x1fffe8), (23, 0x7ffff3), (22, 0x3fffea), (22, 0x3fffeb),
+    (25, 0x1ffffee), (25, 0x1ffffef), (24, 0xfffff4), (24, 0xfffff5), (26, 0x3ffffea), (23, 0x7ffff4),
+    (26, 0x3ffffeb), (27, 0x7ffffe6), (26, 0x3ffffec), (26, 0x3ffffed), (27, 0x7ffffe7), (27, 0x7ffffe8),
+    (27, 0x7ffffe9), (27, 0x7ffffea), (27, 0x7ffffeb), (28, 0xffffffe), (27, 0x7ffffec), (27, 0x7ffffed),
+    (27, 0x7ffffee), (27, 0x7ffffef), (27, 0x7fffff0), (26, 0x3ffffee), (30, 0x3fffffff),
+];
+
+const EOS: u16 = 256;
+
+pub struct Decoder {
+    table: std::collections::VecDeque<(Vec<u8>, Vec<u8>)>,
+    size: usize,
+    max_size: usize,
+    limit: usize,
+    list_limit: usize,
+}
+
+impl Decoder {
+    pub fn new(limit: usize, list_limit: usize) -> Decoder {
+        Decoder {
+            table: std::collections::VecDeque::new(),
+            size: 0,
+            max_size: limit,
+            limit,
+            list_limit,
+        }
+    }
+
+    pub fn decode(&mut self, mut block: &[u8], headers: &mut Vec<(Vec<u8>, Vec<u8>)>) -> Result<(), err::Error> {
+        let mut first = true;
+        let mut list_size = 0;
+
+        while let Some(&byte) = block.first() {
+            if byte & 0x80 != 0 {
+                let index = decode_integer(&mut block, 7)?;
+                let (name, value) = self.get(index)?;
+                list_size = self.account(list_size, name, value)?;
+                headers.push((name.to_vec(), value.to_vec()));
+            } else if byte & 0xc0 == 0x40 {
+                let (name, value) = self.literal(&mut block, 6)?;
+                list_size = self.account(list_size, &name, &value)?;
+                self.insert(name.clone(), value.clone());
+                headers.push((name, value));
+            } else if byte & 0xe0 == 0x20 {
+                if !first {
+                    return Err(err::Error::Protocol);
+                }
+
+                let size = decode_integer(&mut block, 5)?;
+                if size > self.limit {
+                    return Err(err::Error::Protocol);
+                }
+
+                self.max_size = size;
+                self.evict(0);
+                continue;
+            } else {
+                let (name, value) = self.literal(&mut block, 4)?;
+                list_size = self.account(list_size, &name, &value)?;
+                headers.push((name, value));
+            }
+
+            first = false;
+        }
+
+        Ok(())
+    }
+
+    fn account(&self, list_size: usize, name: &[u8], value: &[u8]) -> Result<usize, err::Error> {
+        let list_size = list_size + name.len() + value.len() + ENTRY_OVERHEAD;
+
+        if list_size > self.list_limit {
+            return Err(err::Error::OutOfBounds);
+        }
+
+        Ok(list_size)
+    }
+
+    fn literal(&self, block: &mut &[u8], prefix: u8) -> Result<(Vec<u8>, Vec<u8>), err::Error> {
+        let index = decode_integer(block, prefix)?;
+
+        let name = if index == 0 {
+            decode_string(block)?
+        } else {
+            self.get(index)?.0.to_vec()
+        };
+
+        Ok((name, decode_string(block)?))
+    }
+
+    fn get(&self, index: usize) -> Result<(&[u8], &[u8]), err::Error> {
+        if index == 0 {
+            Err(err::Error::Protocol)
+        } else if index <= STATIC_TABLE.len() {
+            Ok(STATIC_TABLE[index - 1])
+        } else {
+            let (name, value) = self.table.get(index - STATIC_TABLE.len() - 1).ok_or(err::Error::Protocol)?;
+            Ok((name, value))
+        }
+    }
+
+    fn insert(&mut self, name: Vec<u8>, value: Vec<u8>) {
+        let size = name.len() + value.len() + ENTRY_OVERHEAD;
+        self.evict(size);
+
+        if size <= self.max_size {
+            self.size += size;
+            self.table.push_front((name, value));
+        }
+    }
+
+    fn evict(&mut self, incoming: usize) {
+        while self.size + incoming > self.max_size {
+            let Some((name, value)) = self.table.pop_back() else {
+                break;
+            };
+
+            self.size -= name.len() + value.len() + ENTRY_OVERHEAD;
+        }
+    }
+}
+
+pub fn encode_status(code: u16, out: &mut Vec<u8>) {
+    let mut digits = [0; 3];
+    digits[0] = b'0' + (code / 100 % 10) as u8;
+    digits[1] = b'0' + (code / 10 % 10) as u8;
+    digits[2] = b'0' + (code % 10) as u8;
+
+    for (index, (_, value)) in STATIC_TABLE.iter().enumerate().skip(7).take(7) {
+        if *value == digits {
+            encode_integer(index + 1, 7, 0x80, out);
+            return;
+        }
+    }
+
+    encode_integer(8, 4, 0x00, out);
+    encode_string(&digits, out);
+}
+
+pub fn encode(name: &[u8], value: &[u8], out: &mut Vec<u8>) {
+    let index = STATIC_TABLE.iter().position(|(n, _)| *n == name);
+
+    match index {
+        Some(index) => encode_integer(index + 1, 4, 0x00, out),
+        None => {
+            out.push(0x00);
+            encode_string(name, out);
+        }
+    }
+
+    encode_string(value, out);
+}
+
+fn encode_string(value: &[u8], out: &mut Vec<u8>) {
+    encode_integer(value.len(), 7, 0x00, out);
+    out.extend_from_slice(value);
+}
+
+fn encode_integer(mut value: usize, prefix: u8, flags: u8, out: &mut Vec<u8>) {
+    let max = (1usize << prefix) - 1;
+
+    if value < max {
+        out.push(flags | value as u8);
+        return;
+    }
+
+    out.push(flags | max as u8);
+    value -= max;
+
+    while value >= 128 {
+        out.push((value % 128) as u8 | 0x80);
+        value /= 128;
+    }
+
+    out.push(value as u8);
+}
+
+fn decode_integer(block: &mut &[u8], prefix: u8) -> Result<usize, err::Error> {
+    let (&first, rest) = block.split_first().ok_or(err::Error::Protocol)?;
+    *block = rest;
+
+    let max = (1usize << prefix) - 1;
+    let mut value = first as usize & max;
+
+    if value < max {
+        return Ok(value);
+    }
+
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = block.split_first().ok_or(err::Error::Protocol)?;
+        *block = rest;
+
+        if shift > 28 {
+            return Err(err::Error::Protocol);
+        }
+
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn decode_string(block: &mut &[u8]) -> Result<Vec<u8>, err::Error> {
+    let huffman = block.first().ok_or(err::Error::Protocol)? & 0x80 != 0;
+    let len = decode_integer(block, 7)?;
+
+    if block.len() < len {
+        return Err(err::Error::Protocol);
+    }
+
+    let (bytes, rest) = block.split_at(len);
+    *block = rest;
+
+    if huffman {
+        decode_huffman(bytes)
+    } else {
+        Ok(bytes.to_vec())
+    }
+}
+
+fn decode_huffman(bytes: &[u8]) -> Result<Vec<u8>, err::Error> {
+    let tree = huffman_tree();
+    let mut out = Vec::with_capacity(bytes.len() * 8 / 5);
+    let mut node = 0;
+    let mut depth = 0;
+    let mut ones = true;
+
+    for byte in bytes {
+        for bit in (0..8).rev() {
+            let set = (byte >> bit) & 1;
+            ones &= set == 1;
+            depth += 1;
+
+            let next = tree[node][set as usize];
+            if next == 0 {
+                return Err(err::Error::Protocol);
+            }
+
+            if next & LEAF != 0 {
+                let symbol = next & !LEAF;
+                if symbol == EOS {
+                    return Err(err::Error::Protocol);
+                }
+
+                out.push(symbol as u8);
+                node = 0;
+                depth = 0;
+                ones = true;
+            } else {
+                node = next as usize;
+            }
+        }
+    }
+
+    if depth > 7 || !ones {
+        Err(err::Error::Protocol)
+    } else {
+        Ok(out)
+    }
+}
+
+const LEAF: u16 = 0x8000;
+
+fn huffman_tree() -> &'static Vec<[u16; 2]> {
+    static TREE: std::sync::OnceLock<Vec<[u16; 2]>> = std::sync::OnceLock::new();
+
+    TREE.get_or_init(|| {
+        let mut tree = vec![[0u16; 2]];
+
+        for (symbol, &(len, code)) in HUFFMAN.iter().enumerate() {
+            let mut node = 0;
+
+            for bit in (0..len).rev() {
+                let set = ((code >> bit) & 1) as usize;
+
+                if bit == 0 {
+                    tree[node][set] = LEAF | symbol as u16;
+                } else {
+                    if tree[node][set] == 0 {
+                        tree.push([0, 0]);
+                        tree[node][set] = (tree.len() - 1) as u16;
+                    }
+
+                    node = tree[node][set] as usize;
+                }
+            }
+        }
+
+        tree
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+
+        digits.chunks(2).map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap()).collect()
+    }
+
+    fn check(decoder: &mut Decoder, block: &str, expected: &[(&str, &str)], size: usize) {
+        let mut headers = Vec::new();
+        decoder.decode(&hex(block), &mut headers).unwrap();
+
+        let expected: Vec<(Vec<u8>, Vec<u8>)> = expected.iter().map(|(n, v)| (n.as_bytes().to_vec(), v.as_bytes().to_vec())).collect();
+
+        assert_eq!(headers, expected);
+        assert_eq!(decoder.size, size);
+    }
+
+    #[test]
+    fn integer_representation() {
+        let mut out = Vec::new();
+        encode_integer(10, 5, 0x00, &mut out);
+        encode_integer(1337, 5, 0x00, &mut out);
+        encode_integer(42, 8, 0x00, &mut out);
+
+        assert_eq!(out, hex("0a 1f9a0a 2a"));
+
+        let mut block = &out[..];
+        assert_eq!(decode_integer(&mut block, 5).unwrap(), 10);
+        assert_eq!(decode_integer(&mut block, 5).unwrap(), 1337);
+        assert_eq!(decode_integer(&mut block, 8).unwrap(), 42);
+        assert!(block.is_empty());
+    }
+
+    #[test]
+    fn header_field_representation() {
+        check(&mut Decoder::new(4096, 64 * 1024), "400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572", &[("custom-key", "custom-header")], 55);
+        check(&mut Decoder::new(4096, 64 * 1024), "040c 2f73 616d 706c 652f 7061 7468", &[(":path", "/sample/path")], 0);
+        check(&mut Decoder::new(4096, 64 * 1024), "1008 7061 7373 776f 7264 0673 6563 7265 74", &[("password", "secret")], 0);
+        check(&mut Decoder::new(4096, 64 * 1024), "82", &[(":method", "GET")], 0);
+
+        let mut out = Vec::new();
+        encode(b":path", b"/sample/path", &mut out);
+
+        assert_eq!(out, hex("040c 2f73 616d 706c 652f 7061 7468"));
+    }
+
+    #[test]
+    fn requests_without_huffman() {
+        let mut decoder = Decoder::new(4096, 64 * 1024);
+
+        check(&mut decoder, "8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d", &[
+            (":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"),
+        ], 57);
+        check(&mut decoder, "8286 84be 5808 6e6f 2d63 6163 6865", &[
+            (":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"), ("cache-control", "no-cache"),
+        ], 110);
+        check(&mut decoder, "8287 85bf 400a 6375 7374 6f6d 2d6b 6579 0c63 7573 746f 6d2d 7661 6c75 65", &[
+            (":method", "GET"), (":scheme", "https"), (":path", "/index.html"), (":authority", "www.example.com"), ("custom-key", "custom-value"),
+        ], 164);
+    }
+
+    #[test]
+    fn requests_with_huffman() {
+        let mut decoder = Decoder::new(4096, 64 * 1024);
+
+        check(&mut decoder, "8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff", &[
+            (":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"),
+        ], 57);
+        check(&mut decoder, "8286 84be 5886 a8eb 1064 9cbf", &[
+            (":method", "GET"), (":scheme", "http"), (":path", "/"), (":authority", "www.example.com"), ("cache-control", "no-cache"),
+        ], 110);
+        check(&mut decoder, "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf", &[
+            (":method", "GET"), (":scheme", "https"), (":path", "/index.html"), (":authority", "www.example.com"), ("custom-key", "custom-value"),
+        ], 164);
+    }
+
+    #[test]
+    fn responses_without_huffman() {
+        let mut decoder = Decoder::new(256, 64 * 1024);
+
+        check(&mut decoder, "4803 3330 3258 0770 7269 7661 7465 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 3133 2032 303a 3133 3a32 3120 474d 546e 1768 7474 7073 3a2f 2f77 7777 2e65 7861 6d70 6c65 2e63 6f6d", &[
+            (":status", "302"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"), ("location", "https://www.example.com"),
+        ], 222);
+        check(&mut decoder, "4803 3330 37c1 c0bf", &[
+            (":status", "307"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"), ("location", "https://www.example.com"),
+        ], 222);
+        check(&mut decoder, "88c1 611d 4d6f 6e2c 2032 3120 4f63 7420 3230 3133 2032 303a 3133 3a32 3220 474d 54c0 5a04 677a 6970 7738 666f 6f3d 4153 444a 4b48 514b 425a 584f 5157 454f 5049 5541 5851 5745 4f49 553b 206d 6178 2d61 6765 3d33 3630 303b 2076 6572 7369 6f6e 3d31", &[
+            (":status", "200"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:22 GMT"), ("location", "https://www.example.com"),
+            ("content-encoding", "gzip"), ("set-cookie", "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1"),
+        ], 215);
+    }
+
+    #[test]
+    fn responses_with_huffman() {
+        let mut decoder = Decoder::new(256, 64 * 1024);
+
+        check(&mut decoder, "4882 6402 5885 aec3 771a 4b61 96d0 7abe 9410 54d4 44a8 2005 9504 0b81 66e0 82a6 2d1b ff6e 919d 29ad 1718 63c7 8f0b 97c8 e9ae 82ae 43d3", &[
+            (":status", "302"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"), ("location", "https://www.example.com"),
+        ], 222);
+        check(&mut decoder, "4883 640e ffc1 c0bf", &[
+            (":status", "307"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:21 GMT"), ("location", "https://www.example.com"),
+        ], 222);
+        check(&mut decoder, "88c1 6196 d07a be94 1054 d444 a820 0595 040b 8166 e084 a62d 1bff c05a 839b d9ab 77ad 94e7 821d d7f2 e6c7 b335 dfdf cd5b 3960 d5af 2708 7f36 72c1 ab27 0fb5 291f 9587 3160 65c0 03ed 4ee5 b106 3d50 07", &[
+            (":status", "200"), ("cache-control", "private"), ("date", "Mon, 21 Oct 2013 20:13:22 GMT"), ("location", "https://www.example.com"),
+            ("content-encoding", "gzip"), ("set-cookie", "foo=ASDJKHQKBZXOQWEOPIUAXQWEOIU; max-age=3600; version=1"),
+        ], 215);
+    }
+
+    #[test]
+    fn header_list_size_is_bounded() {
+        let mut block = vec![0x40, 0x01, b'x'];
+        encode_integer(4000, 7, 0x00, &mut block);
+        block.extend(std::iter::repeat_n(b'a', 4000));
+        block.extend(std::iter::repeat_n(0xbe, 16 * 1024));
+
+        let mut headers = Vec::new();
+
+        assert!(matches!(Decoder::new(4096, 16 * 1024).decode(&block, &mut headers), Err(err::Error::OutOfBounds)));
+        assert!(headers.len() < 5);
+    }
+
+    #[test]
+    fn header_list_at_the_limit_is_accepted() {
+        let mut block = Vec::new();
+        encode(b"x-filler", &[b'a'; 60], &mut block);
+
+        let mut headers = Vec::new();
+        Decoder::new(4096, 100).decode(&block, &mut headers).unwrap();
+
+        assert_eq!(headers.len(), 1);
+    }
+
+    #[test]
+    fn status_codes_use_the_static_table() {
+        let mut out = Vec::new();
+        encode_status(200, &mut out);
+        encode_status(302, &mut out);
+
+        let mut headers = Vec::new();
+        Decoder::new(4096, 64 * 1024).decode(&out, &mut headers).unwrap();
+
+        assert_eq!(out[0], 0x88);
+        assert_eq!(headers, vec![(b":status".to_vec(), b"200".to_vec()), (b":status".to_vec(), b"302".to_vec())]);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::http;
+
+#[derive(Default)]
+pub struct Headers<'a> {
+    request: bool,
+    boosted: bool,
+    target: Option<&'a [u8]>,
+    trigger: Option<&'a [u8]>,
+    current_url: Option<&'a [u8]>,
+}
+
+pub enum Action<'a> {
+    Redirect(&'a [u8]),
+    Trigger(&'a [u8]),
+    PushUrl(&'a [u8]),
+    Reswap(&'a [u8]),
+}
+
+impl<'a> Headers<'a> {
+    pub fn from_headers(headers: &[http::Header<'a>]) -> Headers<'a> {
+        let mut htmx = Headers::default();
+
+        for header in headers {
+            if header.is(b"HX-Request") {
+                htmx.request = header.value() == b"true";
+            } else if header.is(b"HX-Boosted") {
+                htmx.boosted = header.value() == b"true";
+            } else if header.is(b"HX-Target") {
+                htmx.target = Some(header.value());
+            } else if header.is(b"HX-Trigger") {
+                htmx.trigger = Some(header.value());
+            } else if header.is(b"HX-Current-URL") {
+                htmx.current_url = Some(header.value());
+            }
+        }
+
+        htmx
+    }
+
+    pub fn is_request(&self) -> bool {
+        self.request
+    }
+
+    pub fn is_boosted(&self) -> bool {
+        self.boosted
+    }
+
+    pub fn is_partial(&self) -> bool {
+        self.request && !self.boosted
+    }
+
+    pub fn target(&self) -> Option<&'a [u8]> {
+        self.target
+    }
+
+    pub fn trigger(&self) -> Option<&'a [u8]> {
+        self.trigger
+    }
+
+    pub fn current_url(&self) -> Option<&'a [u8]> {
+        self.current_url
+    }
+}
+
+impl<'a> Action<'a> {
+    pub fn header(&self) -> http::Header<'a> {
+        match self {
+            Action::Redirect(url) => http::Header::new(b"HX-Redirect", url),
+            Action::Trigger(event) => http::Header::new(b"HX-Trigger", event),
+            Action::PushUrl(url) => http::Header::new(b"HX-Push-Url", url),
+            Action::Reswap(swap) => http::Header::new(b"HX-Reswap", swap),
+        }
+    }
+}
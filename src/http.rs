@@ -1,12 +1,17 @@
-use crate::{alloc, err, manager};
+use crate::{alloc, config, err, manager, net, signal};
+#[cfg(feature = "tls")]
+use crate::tls;
+
+const ACCEPT_BACKOFF: std::time::Duration = std::time::Duration::from_millis(100);
 
 #[derive(Debug, Clone, Copy)]
 pub enum Version {
     One,
     OneOne,
+    Two,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Method {
     Get,
     Post,
@@ -16,6 +21,17 @@ pub enum Content<'a> {
     None,
     Html(&'a [u8]),
     Json(&'a [u8]),
+    Css(&'a [u8]),
+    Js(&'a [u8]),
+    Binary(&'a [u8]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Deflate,
+    Brotli,
 }
 
 impl<'a> Content<'a> {
@@ -23,51 +39,320 @@ impl<'a> Content<'a> {
         match self {
             Content::Html(b) => Some(b),
             Content::Json(b) => Some(b),
+            Content::Css(b) => Some(b),
+            Content::Js(b) => Some(b),
+            Content::Binary(b) => Some(b),
             Content::None => None
         }
     }
+
+    pub fn content_type(&self) -> Option<&'static [u8]> {
+        match self {
+            Content::Html(_) => Some(b"text/html"),
+            Content::Json(_) => Some(b"application/json"),
+            Content::Css(_) => Some(b"text/css"),
+            Content::Js(_) => Some(b"text/javascript"),
+            Content::Binary(_) => Some(b"application/octet-stream"),
+            Content::None => None,
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, Content::Html(_) | Content::Json(_) | Content::Css(_) | Content::Js(_))
+    }
+
+    pub fn from_extension(extension: &[u8], bytes: &'a [u8]) -> Content<'a> {
+        match extension {
+            b"html" | b"htm" => Content::Html(bytes),
+            b"json" => Content::Json(bytes),
+            b"css" => Content::Css(bytes),
+            b"js" | b"mjs" => Content::Js(bytes),
+            _ => Content::Binary(bytes),
+        }
+    }
+}
+
+impl Encoding {
+    const PREFERENCE: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    pub fn name(&self) -> &'static [u8] {
+        match self {
+            Encoding::Identity => b"identity",
+            Encoding::Gzip => b"gzip",
+            Encoding::Deflate => b"deflate",
+            Encoding::Brotli => b"br",
+        }
+    }
+
+    pub fn quality(&self, accept: &[u8]) -> u32 {
+        let mut wildcard = None;
+
+        for item in accept.split(|&b| b == b',') {
+            let mut params = item.split(|&b| b == b';');
+            let name = params.next().unwrap_or_default().trim_ascii();
+
+            let mut quality = 1000;
+            for param in params {
+                if let Some(value) = param.trim_ascii().strip_prefix(b"q=") {
+                    quality = parse_quality(value);
+                }
+            }
+
+            if name.eq_ignore_ascii_case(self.name()) {
+                return quality;
+            } else if name == b"*" {
+                wildcard = Some(quality);
+            }
+        }
+
+        wildcard.unwrap_or(0)
+    }
+
+    pub fn negotiate(accept: Option<&[u8]>, available: &[Encoding]) -> Encoding {
+        let Some(accept) = accept else {
+            return Encoding::Identity;
+        };
+
+        let mut best = Encoding::Identity;
+        let mut best_quality = 0;
+
+        for encoding in Encoding::PREFERENCE {
+            if !available.contains(&encoding) {
+                continue;
+            }
+
+            let quality = encoding.quality(accept);
+            if quality > best_quality {
+                best = encoding;
+                best_quality = quality;
+            }
+        }
+
+        best
+    }
+}
+
+fn parse_quality(value: &[u8]) -> u32 {
+    let mut parts = value.trim_ascii().split(|&b| b == b'.');
+    let integer = parts.next().unwrap_or_default();
+    let fraction = parts.next().unwrap_or_default();
+
+    if integer == b"1" {
+        return 1000;
+    } else if integer != b"0" && !integer.is_empty() {
+        return 0;
+    }
+
+    let mut quality = 0;
+    for i in 0..3 {
+        let digit = fraction.get(i).filter(|d| d.is_ascii_digit()).map_or(0, |d| (d - b'0') as u32);
+        quality = quality * 10 + digit;
+    }
+
+    quality
+}
+
+#[derive(Clone, Copy)]
+pub struct Header<'a> {
+    name: &'a [u8],
+    value: &'a [u8],
+}
+
+impl<'a> Header<'a> {
+    pub fn new(name: &'a [u8], value: &'a [u8]) -> Header<'a> {
+        Header { name, value }
+    }
+
+    pub fn from_bytes(line: &'a [u8]) -> Result<Header<'a>, err::Error> {
+        let Some(colon) = line.iter().position(|&b| b == b':') else {
+            return Err(err::Error::Parsing);
+        };
+
+        Ok(Header {
+            name: line[..colon].trim_ascii(),
+            value: line[colon + 1..].trim_ascii(),
+        })
+    }
+
+    pub fn name(&self) -> &'a [u8] {
+        self.name
+    }
+
+    pub fn value(&self) -> &'a [u8] {
+        self.value
+    }
+
+    pub fn is(&self, name: &[u8]) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
 }
 
 pub struct Connection {
-    listener: std::net::TcpListener,
+    listeners: Vec<net::Listener>,
+    config: config::ServerConfig,
+    #[cfg(feature = "tls")]
+    tls: Option<std::sync::Arc<tls::Tls>>,
 }
 
 impl Connection {
-    pub fn new(addr: &str) -> Result<Connection, err::Error> {
-        let listener = std::net::TcpListener::bind(addr).map_err(|_| err::Error::Connect)?;
-        listener.set_nonblocking(true).map_err(|_| err::Error::Connect)?;
+    pub fn new(config: &config::ServerConfig) -> Result<Connection, err::Error> {
+        Connection::from_listeners(config, net::inherited()?)
+    }
+
+    pub fn from_fd(config: &config::ServerConfig, fd: std::os::fd::RawFd) -> Result<Connection, err::Error> {
+        Connection::from_listeners(config, vec![net::Listener::from_fd(fd)?])
+    }
+
+    pub fn from_listeners(config: &config::ServerConfig, mut inherited: Vec<net::Listener>) -> Result<Connection, err::Error> {
+        let mut listeners = Vec::new();
+
+        for addr in config.socket_addrs()? {
+            match take(&mut inherited, |l| l.local_addr() == Some(addr)) {
+                Some(listener) => listeners.push(listener),
+                None => listeners.push(net::Listener::tcp(addr)?),
+            }
+        }
+
+        for path in config.unix_paths() {
+            match take(&mut inherited, |l| l.unix_path() == Some(path.as_path())) {
+                Some(listener) => listeners.push(listener),
+                None => listeners.push(net::Listener::unix(&path)?),
+            }
+        }
+
+        #[cfg(feature = "tls")]
+        let tls = if config.tls_addresses.is_empty() {
+            None
+        } else {
+            let tls = std::sync::Arc::new(tls::Tls::new(config)?);
+
+            for addr in config.tls_socket_addrs()? {
+                match take(&mut inherited, |l| l.local_addr() == Some(addr)) {
+                    Some(listener) => listeners.push(listener.into_tls(tls.clone())?),
+                    None => listeners.push(net::Listener::tls(addr, tls.clone())?),
+                }
+            }
+
+            Some(tls)
+        };
+
+        #[cfg(not(feature = "tls"))]
+        if !config.tls_addresses.is_empty() {
+            return Err(err::Error::Config);
+        }
+
+        listeners.append(&mut inherited);
+
+        signal::install(&[signal::Signal::Hangup, signal::Signal::Terminate, signal::Signal::Interrupt, signal::Signal::User1, signal::Signal::User2])?;
 
         Ok(Connection {
-            listener,
+            listeners,
+            config: config.clone(),
+            #[cfg(feature = "tls")]
+            tls,
         })
     }
 
-    pub fn handle_connections(&mut self, allocator: &mut alloc::Allocator) -> Result<(), err::Error> {
-        let mut executor = manager::Manager::new(allocator)?;
+    pub fn handle_connections(&mut self, allocator: &alloc::Allocator, routes: &manager::Routes) -> Result<(), err::Error> {
+        let context_allocator = allocator.child(self.config.context_arena_size)?.with_label("context");
+        let mut executor = manager::Manager::new(allocator, &context_allocator, &self.config, routes)?;
+        let mut deadline = None;
+
+        for listener in &self.listeners {
+            executor.listen(listener)?;
+        }
+
+        loop {
+            if signal::take(signal::Signal::Hangup) {
+                self.reload();
+            }
+
+            if signal::take(signal::Signal::Terminate) | signal::take(signal::Signal::Interrupt) {
+                if deadline.is_some() {
+                    return Ok(());
+                }
+
+                deadline = Some(self.drain(&mut executor));
+            }
+
+            if signal::take(signal::Signal::User1) {
+                eprint!("{}", allocator);
+            }
 
-        for stream in self.listener.incoming() {
-            match stream {
-                Ok(stream) => executor.append(manager::RequestHandler::new(stream)?),
-                Err(e) => {
-                    if let std::io::ErrorKind::WouldBlock = e.kind() {
-                        if !executor.has_waiting() {
-                            std::thread::sleep(std::time::Duration::from_millis(10));
-                            continue;
+            if signal::take(signal::Signal::User2) && deadline.is_none() {
+                match net::reexec(&self.listeners) {
+                    Ok(_) => {
+                        for listener in &mut self.listeners {
+                            listener.detach();
                         }
-                    } else {
-                        return Err(err::Error::Parsing);
+
+                        deadline = Some(self.drain(&mut executor));
                     }
-                },
+                    Err(_) => eprintln!("Failed to start the new process, keeping the current one"),
+                }
+            }
+
+            if let Some(deadline) = deadline {
+                if executor.is_empty() || std::time::Instant::now() >= deadline {
+                    return Ok(());
+                }
             }
 
+            if executor.wait(deadline)? {
+                'listeners: for listener in &self.listeners {
+                    while !executor.is_full() || self.config.overload != config::Overload::Pause {
+                        match listener.accept() {
+                            Ok(stream) if executor.is_full() => executor.reject(stream),
+                            Ok(stream) => {
+                                let _ = executor.append(stream);
+                            }
+                            Err(e) if is_exhausted(&e) => {
+                                eprintln!("Failed to accept a connection ({}), pausing the listeners", e);
+                                executor.back_off(ACCEPT_BACKOFF);
+                                break 'listeners;
+                            }
+                            Err(e) => match e.kind() {
+                                std::io::ErrorKind::WouldBlock => break,
+                                std::io::ErrorKind::ConnectionAborted | std::io::ErrorKind::Interrupted => {}
+                                _ => return Err(err::Error::Connect),
+                            },
+                        }
+                    }
+                }
+            }
+
+            executor.advance_timers();
             executor.swap();
-            while executor.next() {}
+            while executor.run_next() {}
         }
+    }
 
-        Ok(())
+    fn drain(&mut self, executor: &mut manager::Manager<'_>) -> std::time::Instant {
+        executor.shutdown();
+        self.listeners.clear();
+
+        std::time::Instant::now() + self.config.shutdown_timeout
+    }
+
+    fn reload(&self) {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            if tls.reload().is_err() {
+                eprintln!("Failed to reload TLS certificates, keeping the previous ones");
+            }
+        }
     }
 }
 
+fn is_exhausted(error: &std::io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM))
+}
+
+fn take(listeners: &mut Vec<net::Listener>, matches: impl Fn(&net::Listener) -> bool) -> Option<net::Listener> {
+    let index = listeners.iter().position(matches)?;
+    Some(listeners.remove(index))
+}
+
 impl Version {
     pub fn from_bytes(opt: Option<&[u8]>) -> Result<Version, err::Error> {
         let Some(bytes) = opt else {
@@ -109,8 +394,11 @@ impl Method {
         }
     }
 
-    pub fn eq(&self, other: &Method) -> bool {
-        *self as u32 == *other as u32
+    pub fn name(&self) -> &'static [u8] {
+        match self {
+            Method::Get => b"GET",
+            Method::Post => b"POST",
+        }
     }
 }
 
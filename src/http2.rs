@@ -0,0 +1,769 @@
+use crate::{http, hpack, response, err};
+
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const FRAME_HEADER_SIZE: usize = 9;
+const MAX_FRAME_SIZE: usize = 16384;
+const MAX_CONCURRENT_STREAMS: usize = 100;
+const HEADER_TABLE_SIZE: usize = 4096;
+const DEFAULT_WINDOW: i64 = 65535;
+const MAX_WINDOW: i64 = (1 << 31) - 1;
+const MAX_HEADER_BLOCK_SIZE: usize = 16 * 1024;
+const MAX_HEADER_LIST_SIZE: usize = 16 * 1024;
+const MAX_BODY_SIZE: usize = 1 << 20;
+const CONNECTION_WINDOW: i64 = 4 * MAX_BODY_SIZE as i64;
+const MAX_SPARE_BUFFERS: usize = 8;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY_FLAG: u8 = 0x20;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorCode {
+    NoError = 0x0,
+    Protocol = 0x1,
+    Internal = 0x2,
+    FlowControl = 0x3,
+    StreamClosed = 0x5,
+    FrameSize = 0x6,
+    RefusedStream = 0x7,
+    Cancel = 0x8,
+    Compression = 0x9,
+    EnhanceYourCalm = 0xb,
+}
+
+pub struct Connection {
+    input: Vec<u8>,
+    output: Vec<u8>,
+    decoder: hpack::Decoder,
+    streams: Vec<Stream>,
+    preface: bool,
+    last_stream: u32,
+    send_window: i64,
+    receive_window: i64,
+    initial_window: i64,
+    max_frame_size: usize,
+    block: Vec<u8>,
+    block_stream: Option<(u32, bool)>,
+    encoded: Vec<u8>,
+    spare: Vec<Vec<u8>>,
+    closing: bool,
+    failed: bool,
+}
+
+pub struct Message {
+    id: u32,
+    headers: Vec<(Vec<u8>, Vec<u8>)>,
+    body: Vec<u8>,
+}
+
+struct Stream {
+    id: u32,
+    headers: Vec<(Vec<u8>, Vec<u8>)>,
+    body: Vec<u8>,
+    decoded: bool,
+    received: bool,
+    dispatched: bool,
+    send_window: i64,
+    receive_window: i64,
+    consumed: usize,
+    pending: Vec<u8>,
+    sent: usize,
+    trailers: Vec<u8>,
+    open: bool,
+    ended: bool,
+}
+
+impl Connection {
+    pub fn new() -> Connection {
+        let mut connection = Connection {
+            input: Vec::new(),
+            output: Vec::new(),
+            decoder: hpack::Decoder::new(HEADER_TABLE_SIZE, MAX_HEADER_LIST_SIZE),
+            streams: Vec::new(),
+            preface: false,
+            last_stream: 0,
+            send_window: DEFAULT_WINDOW,
+            receive_window: CONNECTION_WINDOW,
+            initial_window: DEFAULT_WINDOW,
+            max_frame_size: MAX_FRAME_SIZE,
+            block: Vec::new(),
+            block_stream: None,
+            encoded: Vec::new(),
+            spare: Vec::new(),
+            closing: false,
+            failed: false,
+        };
+
+        let mut settings = Vec::new();
+        setting(&mut settings, SETTINGS_HEADER_TABLE_SIZE, HEADER_TABLE_SIZE as u32);
+        setting(&mut settings, SETTINGS_MAX_CONCURRENT_STREAMS, MAX_CONCURRENT_STREAMS as u32);
+        setting(&mut settings, SETTINGS_INITIAL_WINDOW_SIZE, MAX_BODY_SIZE as u32);
+        setting(&mut settings, SETTINGS_MAX_FRAME_SIZE, MAX_FRAME_SIZE as u32);
+        setting(&mut settings, SETTINGS_MAX_HEADER_LIST_SIZE, MAX_HEADER_LIST_SIZE as u32);
+
+        connection.frame(SETTINGS, 0, 0, &settings);
+        connection.window_update(0, (CONNECTION_WINDOW - DEFAULT_WINDOW) as u32);
+        connection
+    }
+
+    pub fn upgrade(settings: &[u8], headers: Vec<(Vec<u8>, Vec<u8>)>, body: Vec<u8>) -> Result<Connection, err::Error> {
+        let mut connection = Connection::new();
+        let payload = decode_base64url(settings)?;
+
+        if connection.apply_settings(&payload).is_err() {
+            return Err(err::Error::Protocol);
+        }
+
+        connection.last_stream = 1;
+        connection.streams.push(Stream {
+            headers,
+            body,
+            decoded: true,
+            received: true,
+            ..Stream::new(1, connection.initial_window)
+        });
+
+        Ok(connection)
+    }
+
+    pub fn adopt(&mut self, mut input: Vec<u8>, mut output: Vec<u8>) {
+        input.extend_from_slice(&self.input);
+        output.extend_from_slice(&self.output);
+
+        self.input = input;
+        self.output = output;
+    }
+
+    pub fn into_buffers(self) -> (Vec<u8>, Vec<u8>) {
+        (self.input, self.output)
+    }
+
+    pub fn receive(&mut self, bytes: &[u8]) {
+        self.input.extend_from_slice(bytes);
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn consume(&mut self, count: usize) {
+        self.output.drain(..count);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closing && self.output.is_empty() && (self.failed || self.streams.is_empty())
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    pub fn process(&mut self) {
+        if let Err(code) = self.read_frames() {
+            self.go_away(code);
+        }
+    }
+
+    pub fn next_message(&mut self) -> Option<Message> {
+        let stream = self.streams.iter_mut().find(|s| !s.dispatched && s.decoded && s.received)?;
+        stream.dispatched = true;
+
+        let message = Message {
+            id: stream.id,
+            headers: std::mem::take(&mut stream.headers),
+            body: std::mem::take(&mut stream.body),
+        };
+
+        let consumed = stream.consumed;
+        self.release(consumed);
+
+        Some(message)
+    }
+
+    fn read_frames(&mut self) -> Result<(), ErrorCode> {
+        if !self.preface {
+            if self.input.len() < PREFACE.len() {
+                return if PREFACE.starts_with(&self.input) { Ok(()) } else { Err(ErrorCode::Protocol) };
+            }
+
+            if &self.input[..PREFACE.len()] != PREFACE {
+                return Err(ErrorCode::Protocol);
+            }
+
+            self.input.drain(..PREFACE.len());
+            self.preface = true;
+        }
+
+        let input = std::mem::take(&mut self.input);
+        let mut offset = 0;
+        let mut result = Ok(());
+
+        while input.len() - offset >= FRAME_HEADER_SIZE && !self.failed {
+            let header = &input[offset..offset + FRAME_HEADER_SIZE];
+            let length = (header[0] as usize) << 16 | (header[1] as usize) << 8 | header[2] as usize;
+
+            if length > MAX_FRAME_SIZE {
+                result = Err(ErrorCode::FrameSize);
+                break;
+            }
+
+            if input.len() - offset < FRAME_HEADER_SIZE + length {
+                break;
+            }
+
+            let id = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff;
+            let payload = &input[offset + FRAME_HEADER_SIZE..offset + FRAME_HEADER_SIZE + length];
+            offset += FRAME_HEADER_SIZE + length;
+
+            result = self.handle_frame(header[3], header[4], id, payload);
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.input = input;
+        self.input.drain(..offset);
+
+        result
+    }
+
+    fn handle_frame(&mut self, kind: u8, flags: u8, id: u32, payload: &[u8]) -> Result<(), ErrorCode> {
+        if let Some((expected, _)) = self.block_stream {
+            if kind != CONTINUATION || id != expected {
+                return Err(ErrorCode::Protocol);
+            }
+        }
+
+        match kind {
+            DATA => {
+                if id == 0 {
+                    return Err(ErrorCode::Protocol);
+                }
+
+                let data = unpad(flags, payload)?;
+
+                self.receive_window -= payload.len() as i64;
+
+                if self.receive_window < 0 {
+                    return Err(ErrorCode::FlowControl);
+                }
+
+                match self.streams.iter_mut().find(|s| s.id == id && s.decoded && !s.received) {
+                    Some(stream) => {
+                        stream.receive_window -= payload.len() as i64;
+                        stream.consumed += payload.len();
+
+                        stream.body.extend_from_slice(data);
+                        stream.received = flags & END_STREAM != 0;
+
+                        if stream.receive_window < 0 {
+                            self.reset(id, ErrorCode::FlowControl);
+                        } else if stream.receive_window == 0 && !stream.received {
+                            self.reset(id, ErrorCode::EnhanceYourCalm);
+                        }
+                    }
+                    None => {
+                        self.release(payload.len());
+                        self.reset(id, ErrorCode::StreamClosed);
+                    }
+                }
+            }
+            HEADERS => {
+                if id == 0 || id.is_multiple_of(2) {
+                    return Err(ErrorCode::Protocol);
+                }
+
+                let mut fragment = unpad(flags, payload)?;
+
+                if flags & PRIORITY_FLAG != 0 {
+                    fragment = fragment.get(5..).ok_or(ErrorCode::FrameSize)?;
+                }
+
+                let existing = self.streams.iter().any(|s| s.id == id);
+
+                if !existing {
+                    if id <= self.last_stream {
+                        return Err(ErrorCode::Protocol);
+                    }
+
+                    self.last_stream = id;
+                }
+
+                if fragment.len() > MAX_HEADER_BLOCK_SIZE {
+                    return Err(ErrorCode::EnhanceYourCalm);
+                }
+
+                self.block.clear();
+                self.block.extend_from_slice(fragment);
+                self.block_stream = Some((id, flags & END_STREAM != 0));
+
+                if flags & END_HEADERS != 0 {
+                    self.end_headers()?;
+                }
+            }
+            CONTINUATION => {
+                if self.block_stream.is_none() {
+                    return Err(ErrorCode::Protocol);
+                }
+
+                if self.block.len() + payload.len() > MAX_HEADER_BLOCK_SIZE {
+                    return Err(ErrorCode::EnhanceYourCalm);
+                }
+
+                self.block.extend_from_slice(payload);
+
+                if flags & END_HEADERS != 0 {
+                    self.end_headers()?;
+                }
+            }
+            PRIORITY if payload.len() != 5 => return Err(ErrorCode::FrameSize),
+            RST_STREAM => {
+                if id == 0 || payload.len() != 4 {
+                    return Err(ErrorCode::Protocol);
+                }
+
+                self.discard(id);
+            }
+            SETTINGS => {
+                if id != 0 {
+                    return Err(ErrorCode::Protocol);
+                }
+
+                if flags & ACK == 0 {
+                    self.apply_settings(payload)?;
+                    self.frame(SETTINGS, ACK, 0, &[]);
+                } else if !payload.is_empty() {
+                    return Err(ErrorCode::FrameSize);
+                }
+            }
+            PING => {
+                if id != 0 {
+                    return Err(ErrorCode::Protocol);
+                }
+
+                if payload.len() != 8 {
+                    return Err(ErrorCode::FrameSize);
+                }
+
+                if flags & ACK == 0 {
+                    self.frame(PING, ACK, 0, payload);
+                }
+            }
+            GOAWAY => {
+                self.closing = true;
+            }
+            WINDOW_UPDATE => {
+                if payload.len() != 4 {
+                    return Err(ErrorCode::FrameSize);
+                }
+
+                let increment = (u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) & 0x7fff_ffff) as i64;
+
+                if id == 0 {
+                    if increment == 0 || self.send_window + increment > MAX_WINDOW {
+                        return Err(ErrorCode::FlowControl);
+                    }
+
+                    self.send_window += increment;
+                } else if let Some(stream) = self.streams.iter_mut().find(|s| s.id == id) {
+                    if increment == 0 || stream.send_window + increment > MAX_WINDOW {
+                        self.reset(id, ErrorCode::FlowControl);
+                    } else {
+                        stream.send_window += increment;
+                    }
+                }
+            }
+            PUSH_PROMISE => return Err(ErrorCode::Protocol),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn end_headers(&mut self) -> Result<(), ErrorCode> {
+        let Some((id, end_stream)) = self.block_stream.take() else {
+            return Err(ErrorCode::Protocol);
+        };
+
+        let mut headers = Vec::new();
+        self.decoder.decode(&self.block, &mut headers).map_err(|e| match e {
+            err::Error::OutOfBounds => ErrorCode::EnhanceYourCalm,
+            _ => ErrorCode::Compression,
+        })?;
+
+        if let Some(stream) = self.streams.iter_mut().find(|s| s.id == id) {
+            if stream.received || !end_stream {
+                return Err(ErrorCode::Protocol);
+            }
+
+            stream.received = true;
+        } else if self.closing || self.streams.len() >= MAX_CONCURRENT_STREAMS {
+            self.reset(id, ErrorCode::RefusedStream);
+        } else {
+            self.streams.push(Stream {
+                headers,
+                decoded: true,
+                received: end_stream,
+                ..Stream::new(id, self.initial_window)
+            });
+        }
+
+        Ok(())
+    }
+
+    fn apply_settings(&mut self, payload: &[u8]) -> Result<(), ErrorCode> {
+        if !payload.len().is_multiple_of(6) {
+            return Err(ErrorCode::FrameSize);
+        }
+
+        for setting in payload.chunks(6) {
+            let identifier = u16::from_be_bytes([setting[0], setting[1]]);
+            let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+
+            match identifier {
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    if value as i64 > MAX_WINDOW {
+                        return Err(ErrorCode::FlowControl);
+                    }
+
+                    let delta = value as i64 - self.initial_window;
+                    self.initial_window = value as i64;
+
+                    for stream in &mut self.streams {
+                        stream.send_window += delta;
+                    }
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(16384..=16_777_215).contains(&value) {
+                        return Err(ErrorCode::Protocol);
+                    }
+
+                    self.max_frame_size = value as usize;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn respond(&mut self, id: u32, response: &response::HttpResponse) {
+        let content = response.content();
+
+        if self.start(id, response.status().code(), response.headers(), content.is_empty()).is_some() {
+            self.send(id, content);
+        }
+    }
+
+    pub fn open<'h>(&mut self, id: u32, status: u16, headers: impl Iterator<Item = http::Header<'h>>) {
+        if let Some(index) = self.start(id, status, headers, false) {
+            self.streams[index].open = true;
+        }
+    }
+
+    pub fn send(&mut self, id: u32, data: &[u8]) {
+        if let Some(stream) = self.streams.iter_mut().find(|s| s.id == id && !s.ended) {
+            stream.pending.drain(..stream.sent);
+            stream.pending.extend_from_slice(data);
+            stream.sent = 0;
+        }
+    }
+
+    pub fn close<'h>(&mut self, id: u32, trailers: impl Iterator<Item = http::Header<'h>>) {
+        let Some(index) = self.streams.iter().position(|s| s.id == id && s.open) else {
+            return;
+        };
+
+        let mut block = std::mem::take(&mut self.streams[index].trailers);
+        block.clear();
+        encode_fields(trailers, &mut block);
+
+        self.streams[index].trailers = block;
+        self.streams[index].open = false;
+    }
+
+    pub fn backlog(&self, id: u32) -> Option<usize> {
+        self.streams.iter().find(|s| s.id == id && !s.ended).map(|s| s.pending.len() - s.sent)
+    }
+
+    fn start<'h>(&mut self, id: u32, status: u16, headers: impl Iterator<Item = http::Header<'h>>, end: bool) -> Option<usize> {
+        let index = self.streams.iter().position(|s| s.id == id)?;
+
+        let mut block = std::mem::take(&mut self.encoded);
+        block.clear();
+        hpack::encode_status(status, &mut block);
+        encode_fields(headers, &mut block);
+
+        self.headers(id, &block, end);
+        self.encoded = block;
+
+        let mut pending = self.spare.pop().unwrap_or_default();
+        pending.clear();
+
+        self.streams[index].pending = pending;
+        self.streams[index].ended = end;
+
+        Some(index)
+    }
+
+    fn headers(&mut self, id: u32, block: &[u8], end: bool) {
+        let mut chunks = block.chunks(self.max_frame_size).peekable();
+        let mut first = true;
+
+        while let Some(chunk) = chunks.next() {
+            let mut flags = if chunks.peek().is_none() { END_HEADERS } else { 0 };
+
+            if first && end {
+                flags |= END_STREAM;
+            }
+
+            self.frame(if first { HEADERS } else { CONTINUATION }, flags, id, chunk);
+            first = false;
+        }
+    }
+
+    pub fn flush(&mut self) {
+        for index in 0..self.streams.len() {
+            loop {
+                let stream = &self.streams[index];
+                let remaining = stream.pending.len() - stream.sent;
+
+                if stream.ended || remaining == 0 || self.send_window <= 0 || stream.send_window <= 0 {
+                    break;
+                }
+
+                let size = remaining.min(self.max_frame_size).min(self.send_window as usize).min(stream.send_window as usize);
+                let (id, start) = (stream.id, stream.sent);
+                let end = size == remaining && !stream.open && stream.trailers.is_empty();
+
+                frame_header(&mut self.output, DATA, if end { END_STREAM } else { 0 }, id, size);
+                self.output.extend_from_slice(&self.streams[index].pending[start..start + size]);
+
+                self.send_window -= size as i64;
+                self.streams[index].send_window -= size as i64;
+                self.streams[index].sent += size;
+                self.streams[index].ended = end;
+            }
+
+            let stream = &self.streams[index];
+
+            if stream.dispatched && !stream.ended && !stream.open && stream.sent == stream.pending.len() {
+                let (id, trailers) = (stream.id, std::mem::take(&mut self.streams[index].trailers));
+
+                if trailers.is_empty() {
+                    self.frame(DATA, END_STREAM, id, &[]);
+                } else {
+                    self.headers(id, &trailers, true);
+                }
+
+                self.streams[index].trailers = trailers;
+                self.streams[index].ended = true;
+            }
+        }
+
+        let mut index = 0;
+
+        while index < self.streams.len() {
+            if self.streams[index].dispatched && self.streams[index].ended {
+                let stream = self.streams.remove(index);
+
+                if self.spare.len() < MAX_SPARE_BUFFERS {
+                    self.spare.push(stream.pending);
+                }
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    pub fn reset(&mut self, id: u32, code: ErrorCode) {
+        self.frame(RST_STREAM, 0, id, &(code as u32).to_be_bytes());
+        self.discard(id);
+    }
+
+    fn discard(&mut self, id: u32) {
+        let Some(index) = self.streams.iter().position(|s| s.id == id) else {
+            return;
+        };
+
+        let stream = self.streams.remove(index);
+
+        if !stream.dispatched {
+            self.release(stream.consumed);
+        }
+    }
+
+    fn release(&mut self, consumed: usize) {
+        if consumed > 0 {
+            self.receive_window += consumed as i64;
+            self.window_update(0, consumed as u32);
+        }
+    }
+
+    fn window_update(&mut self, id: u32, increment: u32) {
+        self.frame(WINDOW_UPDATE, 0, id, &increment.to_be_bytes());
+    }
+
+    pub fn go_away(&mut self, code: ErrorCode) {
+        if self.failed || (self.closing && matches!(code, ErrorCode::NoError)) {
+            return;
+        }
+
+        let mut payload = [0; 8];
+        payload[..4].copy_from_slice(&self.last_stream.to_be_bytes());
+        payload[4..].copy_from_slice(&(code as u32).to_be_bytes());
+
+        self.frame(GOAWAY, 0, 0, &payload);
+        self.closing = true;
+
+        if !matches!(code, ErrorCode::NoError) {
+            self.failed = true;
+            self.streams.clear();
+        }
+    }
+
+    fn frame(&mut self, kind: u8, flags: u8, id: u32, payload: &[u8]) {
+        frame_header(&mut self.output, kind, flags, id, payload.len());
+        self.output.extend_from_slice(payload);
+    }
+}
+
+impl Default for Connection {
+    fn default() -> Connection {
+        Connection::new()
+    }
+}
+
+impl Stream {
+    fn new(id: u32, window: i64) -> Stream {
+        Stream {
+            id,
+            headers: Vec::new(),
+            body: Vec::new(),
+            decoded: false,
+            received: false,
+            dispatched: false,
+            send_window: window,
+            receive_window: MAX_BODY_SIZE as i64,
+            consumed: 0,
+            pending: Vec::new(),
+            sent: 0,
+            trailers: Vec::new(),
+            open: false,
+            ended: false,
+        }
+    }
+}
+
+impl Message {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn pseudo(&self, name: &[u8]) -> Option<&[u8]> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_slice())
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = http::Header<'_>> {
+        self.headers.iter().filter(|(n, _)| !n.starts_with(b":")).map(|(n, v)| http::Header::new(n, v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+fn encode_fields<'h>(fields: impl Iterator<Item = http::Header<'h>>, out: &mut Vec<u8>) {
+    for field in fields {
+        let name = field.name().to_ascii_lowercase();
+
+        if matches!(name.as_slice(), b"connection" | b"keep-alive" | b"transfer-encoding" | b"upgrade" | b"proxy-connection") {
+            continue;
+        }
+
+        hpack::encode(&name, field.value(), out);
+    }
+}
+
+fn frame_header(out: &mut Vec<u8>, kind: u8, flags: u8, id: u32, length: usize) {
+    out.extend_from_slice(&(length as u32).to_be_bytes()[1..]);
+    out.push(kind);
+    out.push(flags);
+    out.extend_from_slice(&(id & 0x7fff_ffff).to_be_bytes());
+}
+
+fn setting(out: &mut Vec<u8>, identifier: u16, value: u32) {
+    out.extend_from_slice(&identifier.to_be_bytes());
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn unpad(flags: u8, payload: &[u8]) -> Result<&[u8], ErrorCode> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+
+    let (&padding, rest) = payload.split_first().ok_or(ErrorCode::FrameSize)?;
+
+    if padding as usize > rest.len() {
+        return Err(ErrorCode::Protocol);
+    }
+
+    Ok(&rest[..rest.len() - padding as usize])
+}
+
+fn decode_base64url(input: &[u8]) -> Result<Vec<u8>, err::Error> {
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for &byte in input {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'-' | b'+' => 62,
+            b'_' | b'/' => 63,
+            b'=' => break,
+            _ => return Err(err::Error::Parsing),
+        };
+
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    Ok(out)
+}
@@ -1,7 +1,23 @@
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
+
 pub mod alloc;
+pub mod asset;
 pub mod collection;
+pub mod config;
 pub mod err;
+pub mod hash;
 pub mod http;
+pub mod http2;
+pub mod hpack;
+pub mod htmx;
 pub mod request;
 pub mod response;
+pub mod signal;
+pub mod sse;
 pub mod manager;
+pub mod net;
+pub mod template;
+pub mod timer;
+pub mod websocket;
+#[cfg(feature = "tls")]
+pub mod tls;
@@ -1,8 +1,11 @@
-use web::{ http, alloc };
+use web::{ http, alloc, config, manager };
 
 fn main() {
-    let mut allocator = alloc::Allocator::new(20 * 4096);
-    let mut connection = http::Connection::new("127.0.0.1:8080").unwrap();
+    let config = config::ServerConfig::load().unwrap();
+    let allocator = alloc::Allocator::new(config.arena_size);
+    let mut connection = http::Connection::new(&config).unwrap();
 
-    connection.handle_connections(&mut allocator).unwrap();
+    let routes = manager::Routes::new();
+
+    connection.handle_connections(&allocator, &routes).unwrap();
 }
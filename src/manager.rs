@@ -1,180 +1,1230 @@
-use crate::{http, request, response, collection, alloc, err};
-use std::{future::Future, io::{Read, Seek, Write}};
+use crate::{http, http2, asset, config, net, request, response, collection, template, signal, sse, timer, websocket, alloc, err};
+use std::io::{Read, Write};
 
-type Mapping = fn (&mut Context) -> Result<response::HttpResponse, err::Error>;
+const EVENT_HISTORY: usize = 64;
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+const MAX_BODY_SIZE: usize = 1 << 20;
+const READ_BUDGET: usize = 64 * 1024;
+const MAX_PENDING_OUTPUT: usize = 256 * 1024;
+const TIMER_SLOTS: usize = 512;
+const TIMER_RESOLUTION: std::time::Duration = std::time::Duration::from_millis(100);
+const SPARE_BUFFER_SIZE: usize = 64 * 1024;
+const LISTENER: usize = usize::MAX;
+const NOTIFIER: usize = usize::MAX - 1;
 
-pub struct Manager {
-    waiting: collection::Array<std::pin::Pin<Box<RequestHandler>>>,
-    queue: collection::Array<std::pin::Pin<Box<RequestHandler>>>,
-    context: std::sync::Arc<std::sync::Mutex<Context>>,
+pub type Mapping = for<'r> fn (&mut Context, &request::Request, &'r alloc::Allocator<'r>) -> Result<response::HttpResponse<'r>, err::Error>;
+pub type SocketMapping = fn (&mut Context, &mut websocket::Socket) -> Result<(), err::Error>;
+pub type StreamMapping = fn (&mut Context, &request::Request) -> Result<(response::ChunkedResponse, Box<dyn Producer>), err::Error>;
+
+/// Once warmed up, plain HTTP/1 connections are served from slab slots, pooled arenas and
+/// recycled buffers without touching the system allocator. HTTP/2, WebSocket, event stream
+/// and chunked connections still keep their protocol state on the global heap.
+pub struct Manager<'a> {
+    handlers: alloc::Slab<'a, RequestHandler<'a>>,
+    wakers: collection::Array<'a, std::task::Waker>,
+    waiting: collection::Array<'a, usize>,
+    queue: collection::Array<'a, usize>,
+    buffers: collection::Array<'a, Vec<u8>>,
+    context: Context<'a>,
+    rejection: Vec<u8>,
+    poller: net::Poller,
+    ready: std::sync::Arc<Ready>,
+    listeners: Vec<std::os::fd::RawFd>,
+    accepting: bool,
+    pause: bool,
+    backoff: Option<std::time::Instant>,
 }
 
-struct Context {
-    mappings: collection::HashMap<request::RequestHeader, Mapping>,
-    files: collection::Array<collection::Array<u8>>,
-    allocator: alloc::Allocator,
+pub struct Routes {
+    mappings: Vec<(http::Method, Vec<u8>, Mapping)>,
+    sockets: Vec<(Vec<u8>, SocketMapping)>,
+    streams: Vec<(Vec<u8>, StreamMapping)>,
+    channels: Vec<Vec<u8>>,
 }
 
-pub struct RequestHandler {
-    stream: std::sync::Arc<std::sync::Mutex<std::net::TcpStream>>,
+pub struct Context<'a> {
+    mappings: collection::HashMap<'a, request::RequestHeader<'a>, Mapping>,
+    sockets: collection::HashMap<'a, request::RequestHeader<'a>, SocketMapping>,
+    streams: collection::HashMap<'a, request::RequestHeader<'a>, StreamMapping>,
+    templates: template::Engine<'a>,
+    assets: collection::Array<'a, asset::Asset<'a>>,
+    channels: collection::Array<'a, std::sync::Arc<sse::Channel>>,
+    draining: bool,
+    arenas: alloc::Pool<'a>,
+    timers: timer::Wheel<'a>,
+    timeouts: Timeouts,
 }
 
-impl RequestHandler {
-    pub fn new(stream: std::net::TcpStream) -> Result<RequestHandler, err::Error> {
-        stream.set_nonblocking(true).map_err(|_| err::Error::Connect)?;
+pub struct RequestHandler<'a> {
+    stream: net::Stream,
+    session: Session<'a>,
+    waker: std::task::Waker,
+    interest: net::Interest,
+    queued: bool,
+}
 
-        Ok(RequestHandler {
-            stream: std::sync::Arc::new(std::sync::Mutex::new(stream))
-        })
-    }
+struct Ready {
+    keys: std::sync::Mutex<Vec<usize>>,
+    notifier: net::Notifier,
 }
 
-impl Future for RequestHandler {
-    type Output = ();
+struct Task {
+    key: usize,
+    ready: std::sync::Arc<Ready>,
+}
 
-    fn poll(self: std::pin::Pin<&mut Self>, ctx: &mut std::task::Context) -> std::task::Poll<Self::Output> {
-        if let Ok(mut stream) = self.stream.lock() {
-            println!("Trying to pool this shit");
-            let context: &mut std::sync::Arc<std::sync::Mutex<Context>> = unsafe { std::mem::transmute(ctx.waker().data() as *mut std::sync::Arc<std::sync::Mutex<Context>>) };
-            let mut context = context.lock().unwrap();
+struct Session<'a> {
+    protocol: Protocol,
+    buffer: Vec<u8>,
+    output: Vec<u8>,
+    closing: bool,
+    arena: alloc::Allocator<'a>,
+    deadline: Deadline,
+}
 
-            let data: *mut u8 = context.allocator.alloc(1024).unwrap();
+enum Protocol {
+    Http1,
+    Http2(Box<http2::Connection>, Vec<Body>),
+    WebSocket(Box<websocket::Socket>, SocketMapping),
+    EventStream(Box<sse::Subscription>),
+    Chunked(Box<response::ChunkedResponse>, Box<dyn Producer>),
+}
+
+enum Body {
+    Events(u32, sse::Subscription),
+    Chunked(u32, response::ChunkedResponse, Box<dyn Producer>),
+}
 
-            let buffer = unsafe { std::slice::from_raw_parts_mut(data, 1024) };
-            match stream.read(buffer) {
-                Ok(n) => {
-                    let bytes = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr(), n) };
-                    let header = request::RequestHeader::from_bytes(bytes, &mut context.allocator).unwrap();
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Timeout {
+    Header,
+    Body,
+    Write,
+    Idle,
+    KeepAlive,
+    Close,
+}
 
-                    if let Some(mapping) = context.mappings.get(&header) {
-                        let res = mapping(&mut context).unwrap();
-                        stream.write(res.body()).unwrap();
-                    } else {
-                        let res = error(&mut context).unwrap();
-                        stream.write(res.body()).unwrap();
-                    }
+struct Deadline {
+    id: usize,
+    timeout: Option<Timeout>,
+}
 
-                    std::task::Poll::Ready(())
-                },
-                Err(_) => {
-                    context.allocator.dealloc(data, 1024);
-                    std::task::Poll::Pending
+struct Timeouts {
+    header: std::time::Duration,
+    body: std::time::Duration,
+    write: std::time::Duration,
+    idle: std::time::Duration,
+    keep_alive: std::time::Duration,
+    close: std::time::Duration,
+}
+
+pub trait Producer {
+    fn produce(&mut self, context: &mut Context, response: &mut response::ChunkedResponse) -> Result<(), err::Error>;
+}
+
+impl<'a> RequestHandler<'a> {
+    pub fn new(stream: net::Stream, arena: alloc::Allocator<'a>, buffer: Vec<u8>, output: Vec<u8>, waker: std::task::Waker) -> RequestHandler<'a> {
+        RequestHandler {
+            stream,
+            session: Session {
+                protocol: Protocol::Http1,
+                buffer,
+                output,
+                closing: false,
+                arena,
+                deadline: Deadline { id: 0, timeout: None },
+            },
+            waker,
+            interest: net::Interest::READ,
+            queued: false,
+        }
+    }
+
+    fn poll(&mut self, context: &mut Context) -> std::task::Poll<()> {
+        let session = &mut self.session;
+
+        if context.timers.take_expired(session.deadline.id) {
+            if session.deadline.timeout == Some(Timeout::KeepAlive) {
+                session.deadline.timeout = None;
+                return session.drive(&mut self.stream, context, &self.waker);
+            }
+
+            let reading = matches!(session.deadline.timeout, Some(Timeout::Header | Timeout::Body));
+
+            if matches!(session.protocol, Protocol::Http1) && reading && !session.buffer.is_empty() {
+                if let Ok(res) = response::HttpResponse::new(http::Version::OneOne, response::HttpStatus::RequestTimeout, http::Content::None, &session.arena) {
+                    send(&mut self.stream, &mut session.output, &res.parts(true));
                 }
             }
+
+            return std::task::Poll::Ready(());
+        }
+
+        session.drive(&mut self.stream, context, &self.waker)
+    }
+}
+
+impl Session<'_> {
+    fn drive(&mut self, stream: &mut net::Stream, context: &mut Context, waker: &std::task::Waker) -> std::task::Poll<()> {
+        if !self.output.is_empty() {
+            let Some(written) = write_available(stream, &self.output) else {
+                return std::task::Poll::Ready(());
+            };
+
+            self.output.drain(..written);
+
+            if !self.output.is_empty() {
+                context.arm(&mut self.deadline, Timeout::Write);
+                return std::task::Poll::Pending;
+            }
+
+            if self.closing {
+                return std::task::Poll::Ready(());
+            }
+
+            match self.protocol {
+                Protocol::Http1 => context.arm(&mut self.deadline, Timeout::Idle),
+                _ => context.disarm(&mut self.deadline),
+            }
+        } else if self.closing {
+            return std::task::Poll::Ready(());
+        }
+
+        match &mut self.protocol {
+            Protocol::Http1 => self.poll_http1(stream, context, waker),
+            Protocol::Http2(connection, bodies) => poll_http2(connection, bodies, &mut self.arena, &mut self.deadline, stream, context, waker),
+            Protocol::WebSocket(socket, handler) => poll_websocket(socket, *handler, &mut self.deadline, stream, context, waker),
+            Protocol::EventStream(subscription) => poll_events(subscription, &mut self.deadline, stream, context, waker),
+            Protocol::Chunked(response, producer) => poll_chunked(response, producer.as_mut(), &mut self.deadline, stream, context, waker),
+        }
+    }
+
+    fn interest(&self, stream: &net::Stream) -> net::Interest {
+        let pending = !self.output.is_empty() || stream.wants_write() || match &self.protocol {
+            Protocol::Http1 => false,
+            Protocol::Http2(connection, _) => !connection.output().is_empty(),
+            Protocol::WebSocket(socket, _) => !socket.output().is_empty(),
+            Protocol::EventStream(subscription) => !subscription.output().is_empty(),
+            Protocol::Chunked(response, _) => !response.output().is_empty(),
+        };
+
+        let read = match self.protocol {
+            Protocol::Http1 => !pending,
+            Protocol::Chunked(_, _) => false,
+            _ => true,
+        };
+
+        net::Interest { read, write: pending }
+    }
+
+    fn poll_http1(&mut self, stream: &mut net::Stream, context: &mut Context, waker: &std::task::Waker) -> std::task::Poll<()> {
+        let limit = (MAX_HEADER_SIZE + MAX_BODY_SIZE).saturating_sub(self.buffer.len());
+
+        if !read_available(stream, limit, waker, |bytes| self.buffer.extend_from_slice(bytes)) {
+            return std::task::Poll::Ready(());
+        }
+
+        if self.buffer.is_empty() {
+            if context.draining {
+                return std::task::Poll::Ready(());
+            }
+
+            if self.deadline.timeout.is_none() {
+                context.arm(&mut self.deadline, Timeout::Header);
+            }
+
+            return std::task::Poll::Pending;
+        }
+
+        if self.buffer.starts_with(http2::PREFACE) {
+            let mut connection = Box::new(http2::Connection::new());
+            connection.adopt(std::mem::take(&mut self.buffer), std::mem::take(&mut self.output));
+            context.disarm(&mut self.deadline);
+
+            self.protocol = Protocol::Http2(connection, Vec::new());
+            return self.drive(stream, context, waker);
+        }
+
+        let Some(head) = self.buffer.windows(4).position(|w| w == b"\r\n\r\n").map(|p| p + 4) else {
+            if self.buffer.len() > MAX_HEADER_SIZE {
+                return std::task::Poll::Ready(());
+            }
+
+            context.arm(&mut self.deadline, Timeout::Header);
+            return std::task::Poll::Pending;
+        };
+
+        let length = match content_length(&self.buffer[..head]) {
+            Ok(length) if length <= MAX_BODY_SIZE => length,
+            Ok(_) => return std::task::Poll::Ready(()),
+            Err(status) => return self.reject(stream, context, status),
+        };
+
+        let total = head + length;
+
+        if self.buffer.len() < total {
+            context.arm(&mut self.deadline, Timeout::Body);
+            return std::task::Poll::Pending;
+        }
+
+        context.disarm(&mut self.deadline);
+        self.arena.clear();
+
+        let Ok(request) = request::Request::from_bytes(&self.buffer[..total], &self.arena) else {
+            return std::task::Poll::Ready(());
+        };
+
+        if let (Some(handshake), Some(handler)) = (websocket::Handshake::from_request(&request), context.sockets.get(request.header()).copied()) {
+            let Ok(res) = handshake.response(&self.arena) else {
+                return std::task::Poll::Ready(());
+            };
+
+            if !send(stream, &mut self.output, &[res.body()]) {
+                return std::task::Poll::Ready(());
+            }
+
+            drop(res);
+            drop(request);
+            self.buffer.drain(..total);
+
+            let socket = Box::new(handshake.socket(std::mem::take(&mut self.buffer), std::mem::take(&mut self.output)));
+
+            self.protocol = Protocol::WebSocket(socket, handler);
+            return self.drive(stream, context, waker);
+        }
+
+        if let Some(channel) = subscribe(context, &request) {
+            let Ok(res) = response::HttpResponse::event_stream(&self.arena) else {
+                return std::task::Poll::Ready(());
+            };
+
+            if !send(stream, &mut self.output, &[res.body()]) {
+                return std::task::Poll::Ready(());
+            }
+
+            let subscription = Box::new(channel.subscribe(request.get(b"Last-Event-ID")));
+            drop(res);
+            drop(request);
+            self.buffer.clear();
+
+            self.protocol = Protocol::EventStream(subscription);
+            return self.drive(stream, context, waker);
+        }
+
+        if let Some(mapping) = context.streams.get(request.header()).copied() {
+            let Ok((response, producer)) = mapping(context, &request) else {
+                return std::task::Poll::Ready(());
+            };
+
+            drop(request);
+            self.buffer.clear();
+
+            self.protocol = Protocol::Chunked(Box::new(response), producer);
+            return self.drive(stream, context, waker);
+        }
+
+        if let Some(mut connection) = upgrade_http2(&request) {
+            let Ok(res) = response::HttpResponse::switching_protocols(b"h2c", &[], &self.arena) else {
+                return std::task::Poll::Ready(());
+            };
+
+            if !send(stream, &mut self.output, &[res.body()]) {
+                return std::task::Poll::Ready(());
+            }
+
+            drop(res);
+            drop(request);
+            self.buffer.drain(..total);
+            connection.adopt(std::mem::take(&mut self.buffer), std::mem::take(&mut self.output));
+
+            self.protocol = Protocol::Http2(connection, Vec::new());
+            return self.drive(stream, context, waker);
+        }
+
+        let keep_alive = request.keep_alive() && !context.draining;
+
+        let Ok(res) = dispatch(context, &request, &self.arena) else {
+            return std::task::Poll::Ready(());
+        };
+
+        if !send(stream, &mut self.output, &res.parts(!keep_alive)) {
+            return std::task::Poll::Ready(());
+        }
+
+        drop(request);
+        self.buffer.drain(..total);
+        self.closing = !keep_alive;
+
+        if !self.output.is_empty() {
+            context.arm(&mut self.deadline, Timeout::Write);
+        } else if self.closing {
+            return std::task::Poll::Ready(());
         } else {
-            std::task::Poll::Pending
+            context.arm(&mut self.deadline, Timeout::Idle);
+
+            if !self.buffer.is_empty() {
+                waker.wake_by_ref();
+            }
+        }
+
+        std::task::Poll::Pending
+    }
+
+    fn reject(&mut self, stream: &mut net::Stream, context: &mut Context, status: response::HttpStatus) -> std::task::Poll<()> {
+        self.arena.clear();
+        self.buffer.clear();
+        self.closing = true;
+
+        let Ok(res) = response::HttpResponse::new(http::Version::OneOne, status, http::Content::None, &self.arena) else {
+            return std::task::Poll::Ready(());
+        };
+
+        if !send(stream, &mut self.output, &res.parts(true)) || self.output.is_empty() {
+            return std::task::Poll::Ready(());
+        }
+
+        context.arm(&mut self.deadline, Timeout::Write);
+        std::task::Poll::Pending
+    }
+}
+
+fn poll_http2(connection: &mut http2::Connection, bodies: &mut Vec<Body>, arena: &mut alloc::Allocator, deadline: &mut Deadline, stream: &mut net::Stream, context: &mut Context, waker: &std::task::Waker) -> std::task::Poll<()> {
+    let limit = if connection.output().len() < MAX_PENDING_OUTPUT { READ_BUDGET } else { 0 };
+
+    if !read_available(stream, limit, waker, |bytes| connection.receive(bytes)) {
+        return std::task::Poll::Ready(());
+    }
+
+    if context.draining {
+        connection.go_away(http2::ErrorCode::NoError);
+    }
+
+    connection.process();
+
+    while let Some(message) = connection.next_message() {
+        arena.clear();
+
+        if respond_http2(connection, bodies, context, &message, arena).is_err() {
+            connection.reset(message.id(), http2::ErrorCode::Internal);
+        }
+    }
+
+    bodies.retain_mut(|body| poll_body(body, connection, context, waker));
+    connection.flush();
+
+    let Some(written) = write_available(stream, connection.output()) else {
+        return std::task::Poll::Ready(());
+    };
+
+    connection.consume(written);
+
+    if connection.is_closed() {
+        return std::task::Poll::Ready(());
+    }
+
+    let producing = bodies.iter().any(|body| matches!(body, Body::Chunked(id, _, _) if connection.backlog(*id) == Some(0)));
+
+    if producing && connection.output().is_empty() {
+        waker.wake_by_ref();
+    }
+
+    if !connection.output().is_empty() {
+        context.arm(deadline, Timeout::Write);
+    } else if bodies.iter().any(|body| matches!(body, Body::Events(_, _))) {
+        context.arm(deadline, Timeout::KeepAlive);
+    } else if connection.is_idle() {
+        context.arm(deadline, Timeout::Idle);
+    } else {
+        context.disarm(deadline);
+    }
+
+    std::task::Poll::Pending
+}
+
+fn poll_body(body: &mut Body, connection: &mut http2::Connection, context: &mut Context, waker: &std::task::Waker) -> bool {
+    match body {
+        Body::Events(id, subscription) => {
+            let Some(backlog) = connection.backlog(*id) else {
+                return false;
+            };
+
+            if context.draining {
+                connection.close(*id, std::iter::empty());
+                return false;
+            }
+
+            if backlog > sse::MAX_BACKLOG || subscription.poll(waker).is_err() {
+                connection.reset(*id, http2::ErrorCode::Cancel);
+                return false;
+            }
+
+            connection.send(*id, subscription.output());
+            subscription.consume(subscription.output().len());
+
+            true
+        }
+        Body::Chunked(id, response, producer) => {
+            let Some(backlog) = connection.backlog(*id) else {
+                return false;
+            };
+
+            if backlog == 0 && connection.output().is_empty() && !response.is_finished() && producer.produce(context, response).is_err() {
+                connection.reset(*id, http2::ErrorCode::Internal);
+                return false;
+            }
+
+            connection.send(*id, response.output());
+            response.consume(response.output().len());
+
+            if response.is_finished() {
+                connection.close(*id, response.trailers());
+                return false;
+            }
+
+            true
         }
     }
 }
 
-impl Context {
-    fn new(parent_allocator: &mut alloc::Allocator) -> Result<Context, err::Error> {
-        let mut allocator = parent_allocator.child(4 * 4096)?;
-        let mut files = collection::Array::new(20, &mut allocator)?;
-        let mut mappings = collection::HashMap::new(20, &mut allocator)?;
+fn poll_websocket(socket: &mut websocket::Socket, handler: SocketMapping, deadline: &mut Deadline, stream: &mut net::Stream, context: &mut Context, waker: &std::task::Waker) -> std::task::Poll<()> {
+    let limit = if socket.output().len() < MAX_PENDING_OUTPUT { READ_BUDGET } else { 0 };
+    let mut received = false;
+
+    if !read_available(stream, limit, waker, |bytes| {
+        received = true;
+        socket.receive(bytes);
+    }) {
+        return std::task::Poll::Ready(());
+    }
+
+    socket.process();
 
-        files.push(read_file("assets/hello.htmx".into(), &mut allocator)?)?;
-        files.push(read_file("assets/error.htmx".into(), &mut allocator)?)?;
+    if context.draining {
+        socket.close(websocket::CloseCode::GoingAway, b"");
+    }
 
-        mappings.insert(request::RequestHeader::new(http::Method::Get, b"/hello", http::Version::OneOne, &mut allocator), hello as Mapping)?;
-        mappings.insert(request::RequestHeader::new(http::Method::Get, b"/", http::Version::OneOne, &mut allocator), root as Mapping)?;
+    if !socket.is_closing() && handler(context, socket).is_err() {
+        socket.close(websocket::CloseCode::Internal, b"");
+    }
+
+    let Some(written) = write_available(stream, socket.output()) else {
+        return std::task::Poll::Ready(());
+    };
+
+    socket.consume(written);
+
+    if socket.is_closed() {
+        return std::task::Poll::Ready(());
+    }
+
+    if !socket.output().is_empty() {
+        context.arm(deadline, Timeout::Write);
+    } else if socket.is_closing() {
+        context.arm(deadline, Timeout::Close);
+    } else {
+        if received {
+            context.disarm(deadline);
+        }
+
+        context.arm(deadline, Timeout::Idle);
+    }
+
+    std::task::Poll::Pending
+}
+
+fn poll_events(subscription: &mut sse::Subscription, deadline: &mut Deadline, stream: &mut net::Stream, context: &mut Context, waker: &std::task::Waker) -> std::task::Poll<()> {
+    if context.draining || !read_available(stream, READ_BUDGET, waker, |_| {}) {
+        return std::task::Poll::Ready(());
+    }
+
+    if subscription.poll(waker).is_err() {
+        return std::task::Poll::Ready(());
+    }
+
+    let Some(written) = write_available(stream, subscription.output()) else {
+        return std::task::Poll::Ready(());
+    };
+
+    subscription.consume(written);
+
+    if subscription.output().is_empty() {
+        context.arm(deadline, Timeout::KeepAlive);
+    } else {
+        context.arm(deadline, Timeout::Write);
+    }
+
+    std::task::Poll::Pending
+}
+
+fn poll_chunked(response: &mut response::ChunkedResponse, producer: &mut dyn Producer, deadline: &mut Deadline, stream: &mut net::Stream, context: &mut Context, waker: &std::task::Waker) -> std::task::Poll<()> {
+    if response.output().is_empty() && !response.is_finished() && producer.produce(context, response).is_err() {
+        return std::task::Poll::Ready(());
+    }
+
+    let Some(written) = write_available(stream, response.output()) else {
+        return std::task::Poll::Ready(());
+    };
+
+    response.consume(written);
+
+    if response.is_done() {
+        return std::task::Poll::Ready(());
+    }
+
+    if response.output().is_empty() {
+        waker.wake_by_ref();
+    }
+
+    context.track_write(deadline, !response.output().is_empty());
+    std::task::Poll::Pending
+}
+
+fn read_available(stream: &mut net::Stream, limit: usize, waker: &std::task::Waker, mut receive: impl FnMut(&[u8])) -> bool {
+    if limit == 0 {
+        return true;
+    }
+
+    let mut buffer = [0; 4096];
+    let mut remaining = limit.min(READ_BUDGET);
+
+    while remaining > 0 {
+        match stream.read(&mut buffer[..remaining.min(4096)]) {
+            Ok(0) => return false,
+            Ok(n) => {
+                receive(&buffer[..n]);
+                remaining -= n;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return true,
+            Err(_) => return false,
+        }
+    }
+
+    waker.wake_by_ref();
+    true
+}
+
+fn send<const N: usize>(stream: &mut net::Stream, output: &mut Vec<u8>, parts: &[&[u8]; N]) -> bool {
+    let mut slices = [std::io::IoSlice::new(&[]); N];
+    let mut count = 0;
+
+    for part in parts.iter().filter(|p| !p.is_empty()) {
+        slices[count] = std::io::IoSlice::new(part);
+        count += 1;
+    }
+
+    let mut pending = &mut slices[..count];
+
+    while output.is_empty() && !pending.is_empty() {
+        match stream.write_vectored(pending) {
+            Ok(0) => return false,
+            Ok(n) => std::io::IoSlice::advance_slices(&mut pending, n),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(_) => return false,
+        }
+    }
+
+    for slice in pending.iter() {
+        output.extend_from_slice(slice);
+    }
+
+    true
+}
+
+fn write_available(stream: &mut net::Stream, output: &[u8]) -> Option<usize> {
+    let mut written = 0;
+
+    while written < output.len() {
+        match stream.write(&output[written..]) {
+            Ok(0) => return None,
+            Ok(n) => written += n,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+            Err(_) => return None,
+        }
+    }
+
+    Some(written)
+}
+
+fn content_length(head: &[u8]) -> Result<usize, response::HttpStatus> {
+    let mut length = None;
+    let mut encoded = false;
+
+    for header in head.split(|&b| b == b'\n').skip(1).filter_map(|l| http::Header::from_bytes(l).ok()) {
+        if header.is(b"Transfer-Encoding") {
+            encoded = true;
+        } else if header.is(b"Content-Length") {
+            let value = header.value();
+
+            if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+                return Err(response::HttpStatus::BadRequest);
+            }
+
+            let value = std::str::from_utf8(value).ok().and_then(|v| v.parse().ok()).ok_or(response::HttpStatus::BadRequest)?;
+
+            if length.is_some_and(|l| l != value) {
+                return Err(response::HttpStatus::BadRequest);
+            }
+
+            length = Some(value);
+        }
+    }
+
+    match (encoded, length) {
+        (true, Some(_)) => Err(response::HttpStatus::BadRequest),
+        (true, None) => Err(response::HttpStatus::NotImplemented),
+        (false, length) => Ok(length.unwrap_or(0)),
+    }
+}
+
+fn subscribe(context: &Context, request: &request::Request) -> Option<std::sync::Arc<sse::Channel>> {
+    if request.header().method() != http::Method::Get {
+        return None;
+    }
+
+    context.channels.slice().iter().find(|c| c.path() == request.header().end_point()).cloned()
+}
+
+fn upgrade_http2(request: &request::Request) -> Option<Box<http2::Connection>> {
+    let upgrade = request.get(b"Upgrade")?;
+    let settings = request.get(b"HTTP2-Settings")?;
+    let connection = request.get(b"Connection")?;
+
+    if !upgrade.eq_ignore_ascii_case(b"h2c") || !connection.to_ascii_lowercase().windows(7).any(|w| w == b"upgrade") {
+        return None;
+    }
+
+    let mut headers = vec![
+        (b":method".to_vec(), request.header().method().name().to_vec()),
+        (b":path".to_vec(), request.header().end_point().to_vec()),
+        (b":scheme".to_vec(), b"http".to_vec()),
+    ];
+
+    for header in request.headers() {
+        let name = header.name().to_ascii_lowercase();
+
+        match name.as_slice() {
+            b"host" => headers.push((b":authority".to_vec(), header.value().to_vec())),
+            b"connection" | b"upgrade" | b"http2-settings" | b"keep-alive" | b"transfer-encoding" => {}
+            _ => headers.push((name, header.value().to_vec())),
+        }
+    }
+
+    http2::Connection::upgrade(settings, headers, request.body().to_vec()).ok().map(Box::new)
+}
+
+fn dispatch<'r>(context: &mut Context, request: &request::Request, arena: &'r alloc::Allocator<'r>) -> Result<response::HttpResponse<'r>, err::Error> {
+    if let Some(mapping) = context.mappings.get(request.header()).copied() {
+        mapping(context, request, arena)
+    } else {
+        error(context, request, arena)
+    }
+}
+
+fn respond_http2(connection: &mut http2::Connection, bodies: &mut Vec<Body>, context: &mut Context, message: &http2::Message, arena: &alloc::Allocator) -> Result<(), err::Error> {
+    let method = http::Method::from_bytes(message.pseudo(b":method"))?;
+    let path = message.pseudo(b":path").ok_or(err::Error::Parsing)?;
+    let header = request::RequestHeader::new(method, path, http::Version::Two, arena)?;
+
+    let mut fields = collection::Array::new(message.len() + 1, arena)?;
+
+    if let Some(authority) = message.pseudo(b":authority") {
+        fields.push(http::Header::new(b"Host", authority))?;
+    }
+
+    for field in message.fields() {
+        fields.push(field)?;
+    }
+
+    let request = request::Request::from_parts(header, fields.slice(), message.body(), arena)?;
+
+    if let Some(channel) = subscribe(context, &request) {
+        let response = response::HttpResponse::event_stream(arena)?;
+        connection.open(message.id(), response.status().code(), response.headers());
+        bodies.push(Body::Events(message.id(), channel.subscribe(request.get(b"Last-Event-ID"))));
+    } else if let Some(mapping) = context.streams.get(request.header()).copied() {
+        let (response, producer) = mapping(context, &request)?;
+        connection.open(message.id(), response.status().code(), response.headers());
+        bodies.push(Body::Chunked(message.id(), response, producer));
+    } else {
+        connection.respond(message.id(), &dispatch(context, &request, arena)?);
+    }
+
+    Ok(())
+}
+
+impl Routes {
+    pub fn new() -> Routes {
+        Routes {
+            mappings: Vec::new(),
+            sockets: Vec::new(),
+            streams: Vec::new(),
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn mapping(&mut self, method: http::Method, path: &[u8], mapping: Mapping) -> &mut Routes {
+        self.mappings.push((method, path.to_vec(), mapping));
+        self
+    }
+
+    pub fn stream(&mut self, path: &[u8], mapping: StreamMapping) -> &mut Routes {
+        self.streams.push((path.to_vec(), mapping));
+        self
+    }
+
+    pub fn socket(&mut self, path: &[u8], mapping: SocketMapping) -> &mut Routes {
+        self.sockets.push((path.to_vec(), mapping));
+        self
+    }
+
+    pub fn channel(&mut self, path: &[u8]) -> &mut Routes {
+        self.channels.push(path.to_vec());
+        self
+    }
+}
+
+impl Default for Routes {
+    fn default() -> Routes {
+        Routes::new()
+    }
+}
+
+impl<'a> Context<'a> {
+    fn new(parent_allocator: &'a alloc::Allocator<'a>, allocator: &'a alloc::Allocator<'a>, config: &config::ServerConfig, routes: &Routes) -> Result<Context<'a>, err::Error> {
+        let mut templates = template::Engine::new(config.table_size, allocator)?;
+        let mut assets = collection::Array::new(config.table_size, allocator)?;
+        let mut mappings = collection::HashMap::new(config.table_size, allocator)?;
+        let mut sockets = collection::HashMap::new(config.table_size, allocator)?;
+        let mut channels = collection::Array::new(config.table_size, allocator)?;
+        let mut streams = collection::HashMap::new(config.table_size, allocator)?;
+        let arenas = alloc::Pool::new(config.max_connections, config.request_arena_size, config.request_arena_limit, parent_allocator)?;
+        let timers = timer::Wheel::new(config.max_connections, TIMER_SLOTS, TIMER_RESOLUTION, parent_allocator)?;
+
+        for entry in std::fs::read_dir("assets").map_err(|_| err::Error::FileNotFound)? {
+            let path = entry.map_err(|_| err::Error::FileNotFound)?.path();
+
+            if path.extension().is_some_and(|e| e == "htmx") {
+                let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+
+                let name = name.to_owned();
+                templates.compile(name.as_bytes(), asset::read_file(path, allocator)?, allocator)?;
+            } else if path.is_file() && !asset::is_precompressed(&path) {
+                let asset = asset::Asset::load(path, b"/assets/", allocator)?;
+
+                mappings.insert(request::RequestHeader::new(http::Method::Get, asset.path(), http::Version::OneOne, allocator)?, serve_static as Mapping)?;
+                assets.push(asset)?;
+            }
+        }
+
+        mappings.insert(request::RequestHeader::new(http::Method::Get, b"/hello", http::Version::OneOne, allocator)?, hello as Mapping)?;
+        mappings.insert(request::RequestHeader::new(http::Method::Get, b"/", http::Version::OneOne, allocator)?, root as Mapping)?;
+
+        for (method, path, mapping) in &routes.mappings {
+            mappings.insert(request::RequestHeader::new(*method, path, http::Version::OneOne, allocator)?, *mapping)?;
+        }
+
+        for (path, mapping) in &routes.streams {
+            streams.insert(request::RequestHeader::new(http::Method::Get, path, http::Version::OneOne, allocator)?, *mapping)?;
+        }
+
+        for (path, mapping) in &routes.sockets {
+            sockets.insert(request::RequestHeader::new(http::Method::Get, path, http::Version::OneOne, allocator)?, *mapping)?;
+        }
+
+        for path in &routes.channels {
+            channels.push(sse::Channel::new(path, EVENT_HISTORY))?;
+        }
 
         Ok(Context {
             mappings,
-            allocator,
-            files,
+            sockets,
+            streams,
+            templates,
+            assets,
+            channels,
+            draining: false,
+            arenas,
+            timers,
+            timeouts: Timeouts {
+                header: config.header_timeout,
+                body: config.body_timeout,
+                write: config.write_timeout,
+                idle: config.idle_timeout,
+                keep_alive: sse::KEEP_ALIVE,
+                close: websocket::CLOSE_TIMEOUT,
+            },
         })
     }
 }
 
-impl Manager {
-    pub fn new(allocator: &mut alloc::Allocator) -> Result<Manager, err::Error> {
-        let context = std::sync::Arc::new(std::sync::Mutex::new(Context::new(allocator)?));
+impl Context<'_> {
+    pub fn channel(&self, path: &[u8]) -> Option<&sse::Channel> {
+        self.channels.slice().iter().find(|c| c.path() == path).map(|c| c.as_ref())
+    }
+
+    fn arm(&mut self, deadline: &mut Deadline, timeout: Timeout) {
+        if deadline.timeout == Some(timeout) {
+            return;
+        }
+
+        let duration = match timeout {
+            Timeout::Header => self.timeouts.header,
+            Timeout::Body => self.timeouts.body,
+            Timeout::Write => self.timeouts.write,
+            Timeout::Idle => self.timeouts.idle,
+            Timeout::KeepAlive => self.timeouts.keep_alive,
+            Timeout::Close => self.timeouts.close,
+        };
+
+        deadline.timeout = Some(timeout);
+        self.timers.schedule(deadline.id, std::time::Instant::now() + duration);
+    }
+
+    fn disarm(&mut self, deadline: &mut Deadline) {
+        if deadline.timeout.take().is_some() {
+            self.timers.cancel(deadline.id);
+        }
+    }
+
+    fn track_write(&mut self, deadline: &mut Deadline, pending: bool) {
+        if pending {
+            self.arm(deadline, Timeout::Write);
+        } else {
+            self.disarm(deadline);
+        }
+    }
+}
+
+impl<'a> Manager<'a> {
+    pub fn new(allocator: &'a alloc::Allocator<'a>, context_allocator: &'a alloc::Allocator<'a>, config: &config::ServerConfig, routes: &Routes) -> Result<Manager<'a>, err::Error> {
+        let context = Context::new(allocator, context_allocator, config, routes)?;
+        let poller = net::Poller::new(config.max_connections + 2)?;
+        let ready = std::sync::Arc::new(Ready { keys: std::sync::Mutex::new(Vec::with_capacity(config.max_connections)), notifier: net::Notifier::new()? });
+        let mut wakers = collection::Array::new(config.max_connections, allocator)?;
+
+        for key in 0..config.max_connections {
+            wakers.push(std::task::Waker::from(std::sync::Arc::new(Task { key, ready: ready.clone() })))?;
+        }
+
+        poller.add(ready.notifier.fd(), NOTIFIER, net::Interest::READ)?;
+        signal::notify(ready.notifier.fd());
 
         Ok(Manager {
-            waiting: collection::Array::new(20, allocator)?,
-            queue: collection::Array::new(20, allocator)?,
+            handlers: alloc::Slab::new(config.max_connections, allocator)?,
+            wakers,
+            waiting: collection::Array::new(config.max_connections, allocator)?,
+            queue: collection::Array::new(config.max_connections, allocator)?,
+            buffers: collection::Array::new(2 * config.max_connections, allocator)?,
             context,
+            rejection: format!("HTTP/1.1 503 Service Unavailable\r\nRetry-After: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", config.retry_after).into_bytes(),
+            poller,
+            ready,
+            listeners: Vec::new(),
+            accepting: true,
+            pause: config.overload == config::Overload::Pause,
+            backoff: None,
         })
     }
 
-    pub fn next(&mut self) -> bool {
-        if let Some(mut f) = self.queue.pop() {
-            let waker = unsafe { std::task::Waker::from_raw(pointer(&mut self.context.clone())) };
-            let mut context = std::task::Context::from_waker(&waker);
+    pub fn listen(&mut self, listener: &net::Listener) -> Result<(), err::Error> {
+        self.poller.add(listener.fd(), LISTENER, net::Interest::READ)?;
+        self.listeners.push(listener.fd());
 
-            if f.as_mut().poll(&mut context).is_pending() {
-                self.waiting.push(f).unwrap();
+        Ok(())
+    }
+
+    pub fn wait(&mut self, limit: Option<std::time::Instant>) -> Result<bool, err::Error> {
+        let now = std::time::Instant::now();
+
+        if self.backoff.is_some_and(|b| now >= b) {
+            self.backoff = None;
+        }
+
+        let accepting = !(self.pause && self.is_full()) && self.backoff.is_none();
+
+        if accepting != self.accepting {
+            for fd in &self.listeners {
+                self.poller.modify(*fd, LISTENER, if accepting { net::Interest::READ } else { net::Interest::NONE })?;
             }
 
-            true
+            self.accepting = accepting;
+        }
+
+        let timeout = if self.waiting.is_empty() {
+            self.context.timers.next_deadline().into_iter().chain(limit).chain(self.backoff).min().map(|d| d.saturating_duration_since(now))
         } else {
-            false
-            //self.swap()
+            Some(std::time::Duration::ZERO)
+        };
+
+        let mut accept = false;
+        let mut notified = false;
+
+        for index in 0..self.poller.wait(timeout)? {
+            match self.poller.token(index) {
+                LISTENER => accept = true,
+                NOTIFIER => notified = true,
+                key => self.queue(key),
+            }
+        }
+
+        if notified {
+            self.ready.notifier.reset();
+
+            let ready = self.ready.clone();
+            for key in ready.keys.lock().unwrap().drain(..) {
+                self.queue(key);
+            }
+        }
+
+        Ok(accept)
+    }
+
+    pub fn run_next(&mut self) -> bool {
+        let Some(key) = self.queue.pop() else {
+            return false;
+        };
+
+        let Some(handler) = self.handlers.get_mut(key) else {
+            return true;
+        };
+
+        handler.queued = false;
+
+        if handler.poll(&mut self.context).is_pending() {
+            let interest = handler.session.interest(&handler.stream);
+
+            if interest == handler.interest {
+                return true;
+            }
+
+            if self.poller.modify(handler.stream.fd(), key, interest).is_ok() {
+                handler.interest = interest;
+                return true;
+            }
+        }
+
+        self.close(key);
+        true
+    }
+
+    fn queue(&mut self, key: usize) {
+        let Some(handler) = self.handlers.get_mut(key).filter(|h| !h.queued) else {
+            return;
+        };
+
+        handler.queued = true;
+
+        if self.waiting.push(key).is_err() {
+            eprintln!("Dropping a connection, the executor is over capacity");
+            self.close(key);
+        }
+    }
+
+    fn close(&mut self, key: usize) {
+        if let Some(handler) = self.handlers.remove(key) {
+            self.recycle(handler);
+        }
+    }
+
+    fn recycle(&mut self, handler: RequestHandler<'a>) {
+        let _ = self.poller.delete(handler.stream.fd());
+        let session = handler.session;
+
+        self.context.timers.cancel(session.deadline.id);
+
+        if self.context.arenas.release(session.arena).is_err() {
+            eprintln!("Dropping a request arena, the pool is already full");
+        }
+
+        let buffers = match session.protocol {
+            Protocol::WebSocket(socket, _) => socket.into_buffers(),
+            Protocol::Http2(connection, _) => connection.into_buffers(),
+            _ => (session.buffer, session.output),
+        };
+
+        for mut buffer in [buffers.0, buffers.1] {
+            if buffer.capacity() <= SPARE_BUFFER_SIZE {
+                buffer.clear();
+                let _ = self.buffers.push(buffer);
+            }
+        }
+    }
+
+    pub fn shutdown(&mut self) {
+        self.context.draining = true;
+
+        for fd in self.listeners.drain(..) {
+            let _ = self.poller.delete(fd);
         }
+
+        for key in 0..self.handlers.capacity() {
+            self.queue(key);
+        }
+    }
+
+    pub fn back_off(&mut self, duration: std::time::Duration) {
+        self.backoff = Some(std::time::Instant::now() + duration);
     }
 
     pub fn swap(&mut self) {
         std::mem::swap(&mut self.waiting, &mut self.queue);
     }
 
-    pub fn has_waiting(&self) -> bool {
-        self.waiting.len() > 0
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
     }
 
-    pub fn append(&mut self, fut: RequestHandler) {
-        self.waiting.push(Box::pin(fut)).unwrap();
+    pub fn is_full(&self) -> bool {
+        self.handlers.is_full()
+    }
+
+    pub fn append(&mut self, stream: net::Stream) -> Result<(), err::Error> {
+        if self.is_full() {
+            return Err(err::Error::OutOfBounds);
+        }
+
+        stream.set_nonblocking(true).map_err(|_| err::Error::Connect)?;
+
+        let arena = self.context.arenas.acquire().ok_or(err::Error::Allocation)?;
+        let buffer = self.buffers.pop().unwrap_or_default();
+        let output = self.buffers.pop().unwrap_or_default();
+        let fd = stream.fd();
+
+        let key = self.handlers.insert(RequestHandler::new(stream, arena, buffer, output, std::task::Waker::noop().clone()))?;
+
+        if let Some(handler) = self.handlers.get_mut(key) {
+            handler.session.deadline.id = key;
+            handler.waker.clone_from(&self.wakers[key]);
+        }
+
+        if let Err(e) = self.poller.add(fd, key, net::Interest::READ) {
+            self.close(key);
+            return Err(e);
+        }
+
+        self.queue(key);
+        Ok(())
+    }
+
+    pub fn advance_timers(&mut self) {
+        self.context.timers.advance(std::time::Instant::now());
+
+        let mut expired = self.context.timers.first_expired();
+
+        while let Some(key) = expired {
+            expired = self.context.timers.next_expired(key);
+            self.queue(key);
+        }
     }
-}
 
-fn pointer<T>(data: &mut T) -> std::task::RawWaker {
-    std::task::RawWaker::new((data as *const T) as *const (), &VTABLE)
+    pub fn reject(&self, mut stream: net::Stream) {
+        if stream.set_nonblocking(true).is_ok() {
+            let _ = stream.write(&self.rejection);
+        }
+    }
 }
 
-static VTABLE: std::task::RawWakerVTable = std::task::RawWakerVTable::new(clone_fn, wake_fn, wake_by_ref_fn, drop_fn);
-const unsafe fn clone_fn(data: *const ()) -> std::task::RawWaker {
-    std::task::RawWaker::new(data, &VTABLE)
+impl Drop for Manager<'_> {
+    fn drop(&mut self) {
+        signal::notify(-1);
+    }
 }
 
-const unsafe fn wake_fn(_: *const ()) {
-    //let data = &*(data as *const DataType);
+impl std::task::Wake for Task {
+    fn wake(self: std::sync::Arc<Task>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &std::sync::Arc<Task>) {
+        let mut keys = self.ready.keys.lock().unwrap();
+
+        if !keys.contains(&self.key) {
+            keys.push(self.key);
+            self.ready.notifier.notify();
+        }
+    }
 }
 
-const unsafe fn wake_by_ref_fn(_: *const ()) {
-    // println!("Wake by ref fn");
+fn root<'r>(context: &mut Context, request: &request::Request, arena: &'r alloc::Allocator<'r>) -> Result<response::HttpResponse<'r>, err::Error> {
+    hello(context, request, arena)
 }
 
-const unsafe fn drop_fn(_: *const  ()) {
-    // println!("Drop fn");
+fn hello<'r>(context: &mut Context, request: &request::Request, arena: &'r alloc::Allocator<'r>) -> Result<response::HttpResponse<'r>, err::Error> {
+    let mut values = template::Context::new(1, arena)?;
+    values.insert(b"name", template::Value::Text(b"world"))?;
+
+    render(context, request, b"hello", response::HttpStatus::Ok, &values, arena)
 }
 
-fn root(context: &mut Context) -> Result<response::HttpResponse, err::Error> {
-    Ok(response::HttpResponse::new(http::Version::OneOne, response::HttpStatus::Ok, http::Content::Html(context.files.at(0)?.slice()), &mut context.allocator)?)
+fn error<'r>(context: &mut Context, request: &request::Request, arena: &'r alloc::Allocator<'r>) -> Result<response::HttpResponse<'r>, err::Error> {
+    let values = template::Context::new(0, arena)?;
+
+    render(context, request, b"error", response::HttpStatus::Error, &values, arena)
 }
 
-fn hello(context: &mut Context) -> Result<response::HttpResponse, err::Error> {
-    Ok(response::HttpResponse::new(http::Version::OneOne, response::HttpStatus::Ok, http::Content::Html(context.files.at(0)?.slice()), &mut context.allocator)?)
+fn render<'r>(context: &mut Context, request: &request::Request, name: &[u8], status: response::HttpStatus, values: &template::Context, arena: &'r alloc::Allocator<'r>) -> Result<response::HttpResponse<'r>, err::Error> {
+    let mut page = collection::Array::new(2048, arena)?;
+
+    if request.htmx().is_partial() {
+        context.templates.render_block(name, b"content", values, &mut page)?;
+    } else {
+        context.templates.render(name, values, &mut page)?;
+    }
+
+    let headers = [http::Header::new(b"Vary", b"HX-Request")];
+    let encoding = request.encoding(&[http::Encoding::Brotli, http::Encoding::Gzip, http::Encoding::Deflate]);
+
+    response::HttpResponse::encoded(http::Version::OneOne, status, &headers, http::Content::Html(page.slice()), encoding, arena)
 }
 
-fn error(context: &mut Context) -> Result<response::HttpResponse, err::Error> {
-    Ok(response::HttpResponse::new(http::Version::OneOne, response::HttpStatus::Error, http::Content::Html(context.files.at(1)?.slice()), &mut context.allocator)?)
+fn serve_static<'r>(context: &mut Context, request: &request::Request, arena: &'r alloc::Allocator<'r>) -> Result<response::HttpResponse<'r>, err::Error> {
+    let Some(asset) = context.assets.slice().iter().find(|a| a.path() == request.header().end_point()) else {
+        return error(context, request, arena);
+    };
+
+    let encoding = request.encoding(&asset.encodings());
+
+    if let Some(bytes) = asset.encoded(encoding).filter(|_| encoding != http::Encoding::Identity) {
+        response::HttpResponse::precompressed(http::Version::OneOne, response::HttpStatus::Ok, &[], asset.content(), encoding, bytes, arena)
+    } else {
+        let encoding = request.encoding(&[http::Encoding::Brotli, http::Encoding::Gzip, http::Encoding::Deflate]);
+        response::HttpResponse::encoded(http::Version::OneOne, response::HttpStatus::Ok, &[], asset.content(), encoding, arena)
+    }
 }
 
-fn read_file(path: std::path::PathBuf, allocator: &mut alloc::Allocator) -> Result<collection::Array<u8>, err::Error> {
-    let mut file = std::fs::File::open(path).map_err(|_| err::Error::FileNotFound)?;
-    file.seek(std::io::SeekFrom::End(0)).map_err(|_| err::Error::OutOfBounds)?;
-    let size = file.stream_position().map_err(|_| err::Error::OutOfBounds)?;
-    file.seek(std::io::SeekFrom::Start(0)).map_err(|_| err::Error::OutOfBounds)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn length(head: &[u8]) -> Result<usize, u16> {
+        content_length(head).map_err(|status| status.code())
+    }
 
-    let mut bytes = collection::Array::new(size as usize, allocator)?;
-    bytes.zero();
+    #[test]
+    fn reads_the_content_length() {
+        assert_eq!(length(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"), Ok(0));
+        assert_eq!(length(b"POST / HTTP/1.1\r\ncontent-length: 12\r\n\r\n"), Ok(12));
+        assert_eq!(length(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\n"), Ok(5));
+        assert_eq!(length(b"GET /?Transfer-Encoding:chunked HTTP/1.1\r\n\r\n"), Ok(0));
+    }
 
-    let slice = bytes.slice_mut();
-    let total = file.read(slice).map_err(|_| err::Error::OutOfBounds)?;
+    #[test]
+    fn rejects_ambiguous_lengths() {
+        assert_eq!(length(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n"), Err(400));
+        assert_eq!(length(b"POST / HTTP/1.1\r\nContent-Length: +5\r\n\r\n"), Err(400));
+        assert_eq!(length(b"POST / HTTP/1.1\r\nContent-Length: 5, 5\r\n\r\n"), Err(400));
+        assert_eq!(length(b"POST / HTTP/1.1\r\nContent-Length:\r\n\r\n"), Err(400));
+        assert_eq!(length(b"POST / HTTP/1.1\r\nContent-Length: 99999999999999999999999\r\n\r\n"), Err(400));
+    }
 
-    if total != size as usize {
-        Err(err::Error::OutOfBounds)
-    } else {
-        Ok(bytes)
+    #[test]
+    fn rejects_transfer_encodings() {
+        assert_eq!(length(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"), Err(501));
+        assert_eq!(length(b"POST / HTTP/1.1\r\ntransfer-encoding : gzip, chunked\r\n\r\n"), Err(501));
+        assert_eq!(length(b"POST / HTTP/1.1\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n"), Err(400));
+        assert_eq!(length(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\n"), Err(400));
     }
 }
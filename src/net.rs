@@ -0,0 +1,376 @@
+use crate::err;
+#[cfg(feature = "tls")]
+use crate::tls;
+
+const LISTEN_FDS_START: std::os::fd::RawFd = 3;
+
+pub enum Listener {
+    Tcp(std::net::TcpListener),
+    Unix(std::os::unix::net::UnixListener, Option<std::path::PathBuf>),
+    #[cfg(feature = "tls")]
+    Tls(std::net::TcpListener, std::sync::Arc<tls::Tls>),
+}
+
+pub enum Stream {
+    Tcp(std::net::TcpStream),
+    Unix(std::os::unix::net::UnixStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ServerConnection, std::net::TcpStream>>),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interest {
+    pub read: bool,
+    pub write: bool,
+}
+
+pub struct Poller {
+    fd: std::os::fd::OwnedFd,
+    events: Vec<libc::epoll_event>,
+}
+
+pub struct Notifier {
+    fd: std::os::fd::OwnedFd,
+}
+
+impl Listener {
+    pub fn tcp(addr: std::net::SocketAddr) -> Result<Listener, err::Error> {
+        Ok(Listener::Tcp(bind(addr)?))
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn tls(addr: std::net::SocketAddr, tls: std::sync::Arc<tls::Tls>) -> Result<Listener, err::Error> {
+        Ok(Listener::Tls(bind(addr)?, tls))
+    }
+
+    pub fn unix(path: &std::path::Path) -> Result<Listener, err::Error> {
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if std::os::unix::fs::FileTypeExt::is_socket(&metadata.file_type()) {
+                std::fs::remove_file(path).map_err(|_| err::Error::Connect)?;
+            }
+        }
+
+        let listener = std::os::unix::net::UnixListener::bind(path).map_err(|_| err::Error::Connect)?;
+        listener.set_nonblocking(true).map_err(|_| err::Error::Connect)?;
+
+        Ok(Listener::Unix(listener, Some(path.to_owned())))
+    }
+
+    pub fn from_fd(fd: std::os::fd::RawFd) -> Result<Listener, err::Error> {
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+            return Err(err::Error::Connect);
+        }
+
+        let socket = unsafe { <socket2::Socket as std::os::fd::FromRawFd>::from_raw_fd(fd) };
+        let addr = socket.local_addr().map_err(|_| err::Error::Connect)?;
+
+        socket.set_nonblocking(true).map_err(|_| err::Error::Connect)?;
+
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(err::Error::Connect);
+        }
+
+        if addr.is_unix() {
+            let path = addr.as_pathname().map(|p| p.to_owned());
+            let listener = std::os::unix::net::UnixListener::from(std::os::fd::OwnedFd::from(socket));
+
+            Ok(Listener::Unix(listener, path))
+        } else {
+            Ok(Listener::Tcp(socket.into()))
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn into_tls(self, tls: std::sync::Arc<tls::Tls>) -> Result<Listener, err::Error> {
+        match &self {
+            Listener::Tcp(listener) => Ok(Listener::Tls(listener.try_clone().map_err(|_| err::Error::Connect)?, tls)),
+            _ => Err(err::Error::Connect),
+        }
+    }
+
+    pub fn local_addr(&self) -> Option<std::net::SocketAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().ok(),
+            Listener::Unix(_, _) => None,
+            #[cfg(feature = "tls")]
+            Listener::Tls(listener, _) => listener.local_addr().ok(),
+        }
+    }
+
+    pub fn unix_path(&self) -> Option<&std::path::Path> {
+        match self {
+            Listener::Unix(_, path) => path.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn fd(&self) -> std::os::fd::RawFd {
+        match self {
+            Listener::Tcp(listener) => std::os::fd::AsRawFd::as_raw_fd(listener),
+            Listener::Unix(listener, _) => std::os::fd::AsRawFd::as_raw_fd(listener),
+            #[cfg(feature = "tls")]
+            Listener::Tls(listener, _) => std::os::fd::AsRawFd::as_raw_fd(listener),
+        }
+    }
+
+    pub fn detach(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            *path = None;
+        }
+    }
+
+    pub fn accept(&self) -> std::io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Stream::Tcp(stream)),
+            Listener::Unix(listener, _) => listener.accept().map(|(stream, _)| Stream::Unix(stream)),
+            #[cfg(feature = "tls")]
+            Listener::Tls(listener, tls) => {
+                let (stream, _) = listener.accept()?;
+                tls.accept(stream).map_err(|_| std::io::ErrorKind::ConnectionAborted.into())
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, Some(path)) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl Stream {
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.sock.set_nonblocking(nonblocking),
+        }
+    }
+
+    pub fn fd(&self) -> std::os::fd::RawFd {
+        match self {
+            Stream::Tcp(stream) => std::os::fd::AsRawFd::as_raw_fd(stream),
+            Stream::Unix(stream) => std::os::fd::AsRawFd::as_raw_fd(stream),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => std::os::fd::AsRawFd::as_raw_fd(&stream.sock),
+        }
+    }
+
+    pub fn wants_write(&self) -> bool {
+        match self {
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.conn.wants_write(),
+            _ => false,
+        }
+    }
+}
+
+impl std::io::Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::Unix(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl std::io::Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::Unix(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[std::io::IoSlice]) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write_vectored(bufs),
+            Stream::Unix(stream) => stream.write_vectored(bufs),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.write_vectored(bufs),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Unix(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+impl Interest {
+    pub const NONE: Interest = Interest { read: false, write: false };
+    pub const READ: Interest = Interest { read: true, write: false };
+
+    fn events(&self) -> u32 {
+        let mut events = libc::EPOLLRDHUP as u32;
+
+        if self.read {
+            events |= libc::EPOLLIN as u32;
+        }
+
+        if self.write {
+            events |= libc::EPOLLOUT as u32;
+        }
+
+        events
+    }
+}
+
+impl Poller {
+    pub fn new(capacity: usize) -> Result<Poller, err::Error> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+
+        if fd < 0 {
+            return Err(err::Error::Connect);
+        }
+
+        Ok(Poller {
+            fd: unsafe { std::os::fd::FromRawFd::from_raw_fd(fd) },
+            events: vec![libc::epoll_event { events: 0, u64: 0 }; capacity.max(1)],
+        })
+    }
+
+    pub fn add(&self, fd: std::os::fd::RawFd, token: usize, interest: Interest) -> Result<(), err::Error> {
+        self.control(libc::EPOLL_CTL_ADD, fd, token, interest)
+    }
+
+    pub fn modify(&self, fd: std::os::fd::RawFd, token: usize, interest: Interest) -> Result<(), err::Error> {
+        self.control(libc::EPOLL_CTL_MOD, fd, token, interest)
+    }
+
+    pub fn delete(&self, fd: std::os::fd::RawFd) -> Result<(), err::Error> {
+        self.control(libc::EPOLL_CTL_DEL, fd, 0, Interest::NONE)
+    }
+
+    pub fn wait(&mut self, timeout: Option<std::time::Duration>) -> Result<usize, err::Error> {
+        let timeout = timeout.map_or(-1, |t| t.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32);
+        let count = unsafe { libc::epoll_wait(std::os::fd::AsRawFd::as_raw_fd(&self.fd), self.events.as_mut_ptr(), self.events.len() as i32, timeout) };
+
+        if count < 0 && std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+            return Err(err::Error::Connect);
+        }
+
+        Ok(count.max(0) as usize)
+    }
+
+    pub fn token(&self, index: usize) -> usize {
+        self.events[index].u64 as usize
+    }
+
+    fn control(&self, operation: libc::c_int, fd: std::os::fd::RawFd, token: usize, interest: Interest) -> Result<(), err::Error> {
+        let mut event = libc::epoll_event { events: interest.events(), u64: token as u64 };
+
+        if unsafe { libc::epoll_ctl(std::os::fd::AsRawFd::as_raw_fd(&self.fd), operation, fd, &mut event) } < 0 {
+            return Err(err::Error::Connect);
+        }
+
+        Ok(())
+    }
+}
+
+impl Notifier {
+    pub fn new() -> Result<Notifier, err::Error> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+
+        if fd < 0 {
+            return Err(err::Error::Connect);
+        }
+
+        Ok(Notifier { fd: unsafe { std::os::fd::FromRawFd::from_raw_fd(fd) } })
+    }
+
+    pub fn fd(&self) -> std::os::fd::RawFd {
+        std::os::fd::AsRawFd::as_raw_fd(&self.fd)
+    }
+
+    pub fn notify(&self) {
+        notify(self.fd());
+    }
+
+    pub fn reset(&self) {
+        let mut count = 0u64;
+        unsafe { libc::read(self.fd(), &mut count as *mut u64 as *mut libc::c_void, 8) };
+    }
+}
+
+pub fn notify(fd: std::os::fd::RawFd) {
+    let count = 1u64;
+    unsafe { libc::write(fd, &count as *const u64 as *const libc::c_void, 8) };
+}
+
+pub fn inherited() -> Result<Vec<Listener>, err::Error> {
+    let Ok(count) = std::env::var("LISTEN_FDS") else {
+        return Ok(Vec::new());
+    };
+
+    if std::env::var("LISTEN_PID").is_ok_and(|pid| pid.parse() != Ok(std::process::id())) {
+        return Ok(Vec::new());
+    }
+
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    let count: std::os::fd::RawFd = count.parse().map_err(|_| err::Error::Config)?;
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count).map(Listener::from_fd).collect()
+}
+
+pub fn reexec(listeners: &[Listener]) -> Result<std::process::Child, err::Error> {
+    let fds: Vec<std::os::fd::RawFd> = listeners.iter().map(|l| l.fd()).collect();
+    let mut temporary = vec![0; fds.len()];
+    let first_free = LISTEN_FDS_START + fds.len() as std::os::fd::RawFd;
+
+    let mut command = std::process::Command::new(std::env::current_exe().map_err(|_| err::Error::FileNotFound)?);
+    command.args(std::env::args_os().skip(1));
+    command.env("LISTEN_FDS", fds.len().to_string()).env_remove("LISTEN_PID").env_remove("LISTEN_FDNAMES");
+
+    unsafe {
+        std::os::unix::process::CommandExt::pre_exec(&mut command, move || {
+            for (fd, copy) in fds.iter().zip(temporary.iter_mut()) {
+                *copy = libc::fcntl(*fd, libc::F_DUPFD_CLOEXEC, first_free);
+
+                if *copy < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+
+            for (target, copy) in (LISTEN_FDS_START..).zip(temporary.iter()) {
+                if libc::dup2(*copy, target) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+
+            Ok(())
+        });
+    }
+
+    command.spawn().map_err(|_| err::Error::Connect)
+}
+
+fn bind(addr: std::net::SocketAddr) -> Result<std::net::TcpListener, err::Error> {
+    let domain = socket2::Domain::for_address(addr);
+    let socket = socket2::Socket::new(domain, socket2::Type::STREAM, Some(socket2::Protocol::TCP)).map_err(|_| err::Error::Connect)?;
+
+    if addr.is_ipv6() {
+        socket.set_only_v6(true).map_err(|_| err::Error::Connect)?;
+    }
+
+    socket.set_reuse_address(true).map_err(|_| err::Error::Connect)?;
+    socket.bind(&addr.into()).map_err(|_| err::Error::Connect)?;
+    socket.listen(128).map_err(|_| err::Error::Connect)?;
+    socket.set_nonblocking(true).map_err(|_| err::Error::Connect)?;
+
+    Ok(socket.into())
+}
@@ -1,23 +1,121 @@
-use crate::{http, alloc, collection, err};
+use crate::{http, htmx, alloc, collection, err};
 
-pub struct RequestHeader {
+pub struct Request<'a> {
+    header: RequestHeader<'a>,
+    headers: collection::Array<'a, http::Header<'a>>,
+    htmx: htmx::Headers<'a>,
+    body: &'a [u8],
+}
+
+pub struct RequestHeader<'a> {
     method: http::Method,
-    end_point: EndPoint,
+    end_point: EndPoint<'a>,
     version: http::Version,
 }
 
-pub struct EndPoint(collection::Array<u8>);
+pub struct EndPoint<'a>(collection::Array<'a, u8>);
 
-impl RequestHeader {
-    pub fn new(method: http::Method, end: &[u8], protocol: http::Version, allocator: &mut alloc::Allocator) -> RequestHeader {
-        let end_point = EndPoint::from_bytes(Some(end), allocator).unwrap();
+impl<'a> Request<'a> {
+    pub fn from_bytes(bytes: &'a [u8], allocator: &'a alloc::Allocator<'a>) -> Result<Request<'a>, err::Error> {
+        let header = RequestHeader::from_bytes(bytes, allocator)?;
 
-        RequestHeader {
-            method, end_point, version: protocol,
+        let end = bytes.windows(4).position(|w| w == b"\r\n\r\n").map_or(bytes.len(), |p| p + 4);
+        let mut lines = bytes[..end].split(|&b| b == b'\n').map(|l| l.strip_suffix(b"\r").unwrap_or(l));
+        lines.next();
+
+        let count = bytes[..end].iter().filter(|&&b| b == b'\n').count();
+        let mut headers = collection::Array::new(count, allocator)?;
+
+        for line in lines {
+            if line.is_empty() {
+                break;
+            }
+
+            headers.push(http::Header::from_bytes(line)?)?;
         }
+
+        let htmx = htmx::Headers::from_headers(headers.slice());
+
+        Ok(Request {
+            header,
+            headers,
+            htmx,
+            body: &bytes[end..],
+        })
     }
 
-    pub fn from_bytes(bytes: &[u8], allocator: &mut alloc::Allocator) -> Result<RequestHeader, err::Error> {
+    pub fn from_parts(header: RequestHeader<'a>, fields: &[http::Header<'a>], body: &'a [u8], allocator: &'a alloc::Allocator<'a>) -> Result<Request<'a>, err::Error> {
+        let mut headers = collection::Array::new(fields.len(), allocator)?;
+        headers.copy(fields)?;
+
+        let htmx = htmx::Headers::from_headers(headers.slice());
+
+        Ok(Request {
+            header,
+            headers,
+            htmx,
+            body,
+        })
+    }
+
+    pub fn header(&self) -> &RequestHeader<'a> {
+        &self.header
+    }
+
+    pub fn get(&self, name: &[u8]) -> Option<&'a [u8]> {
+        self.headers.slice().iter().find(|h| h.is(name)).map(|h| h.value())
+    }
+
+    pub fn headers(&self) -> &[http::Header<'a>] {
+        self.headers.slice()
+    }
+
+    pub fn htmx(&self) -> &htmx::Headers<'a> {
+        &self.htmx
+    }
+
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+
+    pub fn keep_alive(&self) -> bool {
+        let connection = self.get(b"Connection").unwrap_or_default();
+        let has = |token: &[u8]| connection.split(|&b| b == b',').any(|t| t.trim_ascii().eq_ignore_ascii_case(token));
+
+        if has(b"close") {
+            false
+        } else {
+            has(b"keep-alive") || matches!(self.header.version, http::Version::OneOne)
+        }
+    }
+
+    pub fn encoding(&self, available: &[http::Encoding]) -> http::Encoding {
+        http::Encoding::negotiate(self.get(b"Accept-Encoding"), available)
+    }
+}
+
+impl<'a> RequestHeader<'a> {
+    pub fn method(&self) -> http::Method {
+        self.method
+    }
+
+    pub fn version(&self) -> http::Version {
+        self.version
+    }
+
+    pub fn new(method: http::Method, end: &[u8], protocol: http::Version, allocator: &'a alloc::Allocator<'a>) -> Result<RequestHeader<'a>, err::Error> {
+        let end_point = EndPoint::from_bytes(Some(end), allocator)?;
+
+        Ok(RequestHeader {
+            method, end_point, version: protocol,
+        })
+    }
+
+    pub fn end_point(&self) -> &[u8] {
+        self.end_point.0.slice()
+    }
+
+    pub fn from_bytes(bytes: &[u8], allocator: &'a alloc::Allocator<'a>) -> Result<RequestHeader<'a>, err::Error> {
         let mut iter = bytes.split(|&b| b == b' ' || b == b'\r');
 
         Ok(RequestHeader {
@@ -28,33 +126,23 @@ impl RequestHeader {
     }
 }
 
-impl collection::Hash for RequestHeader {
-    fn eq(&self, other: &RequestHeader) -> bool {
-        self.method.eq(&other.method) && self.end_point.0.eq(&other.end_point.0)
+impl PartialEq for RequestHeader<'_> {
+    fn eq(&self, other: &RequestHeader<'_>) -> bool {
+        self.method == other.method && self.end_point.0 == other.end_point.0
     }
+}
 
-    fn hash(&self) -> usize {
-        let slice = self.end_point.0.slice();
-
-        let mut count: usize = 0;
-        for s in slice {
-            count += *s as usize;
-        }
-
-        let v = self.version as usize;
-        let version: usize = v << 30;
-        count |= version;
-
-        count
-    }
+impl Eq for RequestHeader<'_> {}
 
-    fn is_zero(&self) -> bool {
-        self.end_point.0.cap() == 0
+impl std::hash::Hash for RequestHeader<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.method.hash(state);
+        self.end_point.0.slice().hash(state);
     }
 }
 
-impl EndPoint {
-    fn from_bytes(opt: Option<&[u8]>, allocator: &mut alloc::Allocator) -> Result<EndPoint, err::Error> {
+impl<'a> EndPoint<'a> {
+    fn from_bytes(opt: Option<&[u8]>, allocator: &'a alloc::Allocator<'a>) -> Result<EndPoint<'a>, err::Error> {
         let Some(bytes) = opt else {
             return Err(err::Error::Parsing);
         };
@@ -67,7 +155,7 @@ impl EndPoint {
     }
 }
 
-impl std::fmt::Display for RequestHeader {
+impl std::fmt::Display for RequestHeader<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Method: {:?}, ProtocolVersion: {:?}, EndPoint: {}", self.method, self.version, std::str::from_utf8(self.end_point.0.slice()).unwrap())
     }
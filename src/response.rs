@@ -1,20 +1,106 @@
-use crate::{http, collection, alloc, err};
+use crate::{http, htmx, collection, alloc, err};
+use std::io::Write;
 
-#[derive(Debug)]
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
 pub enum HttpStatus {
+    SwitchingProtocols,
     Ok,
+    Found,
+    BadRequest,
     Error,
+    RequestTimeout,
+    NotImplemented,
 }
 
-pub struct HttpResponse {
+pub struct HttpResponse<'a> {
     status: HttpStatus,
     version: http::Version,
-    body: collection::Array<u8>,
+    body: collection::Array<'a, u8>,
+    head: usize,
 }
 
-impl HttpResponse {
-    pub fn new(version: http::Version, status: HttpStatus, content: http::Content, allocator: &mut alloc::Allocator) -> Result<HttpResponse, err::Error> {
-        let mut body: collection::Array<u8> = collection::Array::new(1024, allocator)?;
+pub struct ChunkedResponse {
+    status: HttpStatus,
+    framing: Framing,
+    head: Vec<u8>,
+    output: Vec<u8>,
+    trailers: Vec<u8>,
+    finished: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    Chunked,
+    Close,
+    Frames,
+}
+
+impl HttpStatus {
+    pub fn code(&self) -> u16 {
+        match self {
+            HttpStatus::SwitchingProtocols => 101,
+            HttpStatus::Ok => 200,
+            HttpStatus::Found => 302,
+            HttpStatus::BadRequest => 400,
+            HttpStatus::Error => 404,
+            HttpStatus::RequestTimeout => 408,
+            HttpStatus::NotImplemented => 501,
+        }
+    }
+
+    pub fn reason(&self) -> &'static [u8] {
+        match self {
+            HttpStatus::SwitchingProtocols => b"Switching Protocols",
+            HttpStatus::Ok => b"OK",
+            HttpStatus::Found => b"Found",
+            HttpStatus::BadRequest => b"Bad Request",
+            HttpStatus::Error => b"ERROR",
+            HttpStatus::RequestTimeout => b"Request Timeout",
+            HttpStatus::NotImplemented => b"Not Implemented",
+        }
+    }
+}
+
+impl<'a> HttpResponse<'a> {
+    pub fn new(version: http::Version, status: HttpStatus, content: http::Content, allocator: &'a alloc::Allocator<'a>) -> Result<HttpResponse<'a>, err::Error> {
+        HttpResponse::with_headers(version, status, &[], content, allocator)
+    }
+
+    pub fn with_headers(version: http::Version, status: HttpStatus, headers: &[http::Header], content: http::Content, allocator: &'a alloc::Allocator<'a>) -> Result<HttpResponse<'a>, err::Error> {
+        HttpResponse::encoded(version, status, headers, content, http::Encoding::Identity, allocator)
+    }
+
+    pub fn redirect(htmx: &htmx::Headers, location: &[u8], allocator: &'a alloc::Allocator<'a>) -> Result<HttpResponse<'a>, err::Error> {
+        if htmx.is_request() {
+            let headers = [htmx::Action::Redirect(location).header()];
+            HttpResponse::with_headers(http::Version::OneOne, HttpStatus::Ok, &headers, http::Content::None, allocator)
+        } else {
+            let headers = [http::Header::new(b"Location", location)];
+            HttpResponse::with_headers(http::Version::OneOne, HttpStatus::Found, &headers, http::Content::None, allocator)
+        }
+    }
+
+    pub fn encoded(version: http::Version, status: HttpStatus, headers: &[http::Header], content: http::Content, encoding: http::Encoding, allocator: &'a alloc::Allocator<'a>) -> Result<HttpResponse<'a>, err::Error> {
+        let bytes = content.bytes().unwrap_or_default();
+
+        if encoding != http::Encoding::Identity && content.is_text() && bytes.len() >= COMPRESSION_THRESHOLD {
+            if let Ok(compressed) = compress(bytes, encoding, allocator) {
+                return HttpResponse::build(version, status, headers, content, encoding, compressed.slice(), allocator);
+            }
+        }
+
+        HttpResponse::build(version, status, headers, content, http::Encoding::Identity, bytes, allocator)
+    }
+
+    pub fn precompressed(version: http::Version, status: HttpStatus, headers: &[http::Header], content: http::Content, encoding: http::Encoding, bytes: &[u8], allocator: &'a alloc::Allocator<'a>) -> Result<HttpResponse<'a>, err::Error> {
+        HttpResponse::build(version, status, headers, content, encoding, bytes, allocator)
+    }
+
+    fn build(version: http::Version, status: HttpStatus, headers: &[http::Header], content: http::Content, encoding: http::Encoding, bytes: &[u8], allocator: &'a alloc::Allocator<'a>) -> Result<HttpResponse<'a>, err::Error> {
+        let headers_size: usize = headers.iter().map(|h| h.name().len() + h.value().len() + 4).sum();
+        let mut body: collection::Array<'a, u8> = collection::Array::new(256 + headers_size + bytes.len(), allocator)?;
         body.append_slice(b"HTTP/")?;
 
         match version {
@@ -22,44 +108,284 @@ impl HttpResponse {
             _ => return Err(err::Error::HttpVersion),
         }
 
-        match status {
-            HttpStatus::Ok => body.append_slice(b"200 OK")?,
-            HttpStatus::Error => body.append_slice(b"404 ERROR")?,
+        body.parse(status.code() as usize)?;
+        body.push(b' ')?;
+        body.append_slice(status.reason())?;
+        body.append_slice(b"\r\n")?;
+
+        if let Some(content_type) = content.content_type() {
+            body.append_slice(b"Content-Type: ")?;
+            body.append_slice(content_type)?;
+            body.append_slice(b"\r\n")?;
         }
 
-        if let Some(b) = content.bytes() {
+        if encoding != http::Encoding::Identity {
+            body.append_slice(b"Content-Encoding: ")?;
+            body.append_slice(encoding.name())?;
             body.append_slice(b"\r\n")?;
+        }
 
-            match content {
-                http::Content::Html(_) => {
-                    body.append_slice(b"Content-Type: text/html\r\n")?;
-                }
-                _ => return Err(err::Error::Parsing),
-            }
+        if encoding != http::Encoding::Identity || content.is_text() {
+            body.append_slice(b"Vary: Accept-Encoding\r\n")?;
+        }
 
+        for header in headers {
+            body.append_slice(header.name())?;
+            body.append_slice(b": ")?;
+            body.append_slice(header.value())?;
+            body.append_slice(b"\r\n")?;
+        }
+
+        if bytes.is_empty() {
+            body.append_slice(b"Content-Length: 0\r\n\r\n")?;
+        } else {
             body.append_slice(b"Content-Length: ")?;
-            body.parse(b.len())?;
+            body.parse(bytes.len())?;
             body.append_slice(b"\r\n\r\n")?;
-            body.append_slice(b)?;
-
-            println!("body: {}", std::str::from_utf8(body.slice()).unwrap());
         }
 
+        let head = body.len();
+        body.append_slice(bytes)?;
 
         Ok(HttpResponse {
             status,
             version,
             body,
+            head,
         })
     }
 
+    pub fn switching_protocols(protocol: &[u8], headers: &[http::Header], allocator: &'a alloc::Allocator<'a>) -> Result<HttpResponse<'a>, err::Error> {
+        let size = headers.iter().map(|h| h.name().len() + h.value().len() + 4).sum::<usize>();
+        let mut body: collection::Array<'a, u8> = collection::Array::new(128 + protocol.len() + size, allocator)?;
+        body.append_slice(b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: ")?;
+        body.append_slice(protocol)?;
+        body.append_slice(b"\r\n")?;
+
+        for header in headers {
+            body.append_slice(header.name())?;
+            body.append_slice(b": ")?;
+            body.append_slice(header.value())?;
+            body.append_slice(b"\r\n")?;
+        }
+
+        body.append_slice(b"\r\n")?;
+
+        Ok(HttpResponse {
+            status: HttpStatus::SwitchingProtocols,
+            version: http::Version::OneOne,
+            head: body.len(),
+            body,
+        })
+    }
+
+    pub fn event_stream(allocator: &'a alloc::Allocator<'a>) -> Result<HttpResponse<'a>, err::Error> {
+        let mut body: collection::Array<'a, u8> = collection::Array::new(128, allocator)?;
+        body.append_slice(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n")?;
+
+        Ok(HttpResponse {
+            status: HttpStatus::Ok,
+            version: http::Version::OneOne,
+            head: body.len(),
+            body,
+        })
+    }
+
+    pub fn status(&self) -> HttpStatus {
+        self.status
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = http::Header<'_>> {
+        let head = &self.body.slice()[..self.head];
+
+        head.split(|&b| b == b'\n').skip(1).map(|l| l.strip_suffix(b"\r").unwrap_or(l)).filter_map(|l| http::Header::from_bytes(l).ok())
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.body.slice()[self.head..]
+    }
+
     pub fn body(&self) -> &[u8] {
         self.body.slice()
     }
+
+    pub fn parts(&self, close: bool) -> [&[u8]; 3] {
+        let head = &self.body.slice()[..self.head - 2];
+        let end: &[u8] = if close { b"Connection: close\r\n\r\n" } else { b"\r\n" };
+
+        [head, end, self.content()]
+    }
 }
 
-impl std::fmt::Display for HttpResponse {
+impl ChunkedResponse {
+    pub fn new(version: http::Version, status: HttpStatus, headers: &[http::Header], content_type: &[u8], trailers: &[&[u8]]) -> ChunkedResponse {
+        let framing = match version {
+            http::Version::OneOne => Framing::Chunked,
+            http::Version::One => Framing::Close,
+            http::Version::Two => Framing::Frames,
+        };
+
+        let mut head = Vec::new();
+        write_field(&mut head, b"Content-Type", content_type);
+
+        for header in headers {
+            write_field(&mut head, header.name(), header.value());
+        }
+
+        let mut output = Vec::new();
+
+        if framing != Framing::Frames {
+            output.extend_from_slice(b"HTTP/1.1 ");
+            output.extend_from_slice(status.code().to_string().as_bytes());
+            output.push(b' ');
+            output.extend_from_slice(status.reason());
+            output.extend_from_slice(b"\r\n");
+            output.extend_from_slice(&head);
+
+            if framing == Framing::Chunked {
+                output.extend_from_slice(b"Transfer-Encoding: chunked\r\n");
+
+                if !trailers.is_empty() {
+                    write_field(&mut output, b"Trailer", &trailers.join(&b", "[..]));
+                }
+            } else {
+                output.extend_from_slice(b"Connection: close\r\n");
+            }
+
+            output.extend_from_slice(b"\r\n");
+        }
+
+        ChunkedResponse {
+            status,
+            framing,
+            head,
+            output,
+            trailers: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn status(&self) -> HttpStatus {
+        self.status
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = http::Header<'_>> {
+        fields(&self.head)
+    }
+
+    pub fn trailers(&self) -> impl Iterator<Item = http::Header<'_>> {
+        fields(&self.trailers)
+    }
+
+    pub fn write_chunk(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() || self.finished {
+            return;
+        }
+
+        if self.framing == Framing::Chunked {
+            let _ = write!(self.output, "{:x}\r\n", bytes.len());
+            self.output.extend_from_slice(bytes);
+            self.output.extend_from_slice(b"\r\n");
+        } else {
+            self.output.extend_from_slice(bytes);
+        }
+    }
+
+    pub fn finish(&mut self, trailers: &[http::Header]) {
+        if self.finished {
+            return;
+        }
+
+        match self.framing {
+            Framing::Chunked => {
+                self.output.extend_from_slice(b"0\r\n");
+
+                for trailer in trailers {
+                    write_field(&mut self.output, trailer.name(), trailer.value());
+                }
+
+                self.output.extend_from_slice(b"\r\n");
+            }
+            Framing::Frames => {
+                for trailer in trailers {
+                    write_field(&mut self.trailers, trailer.name(), trailer.value());
+                }
+            }
+            Framing::Close => {}
+        }
+
+        self.finished = true;
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn consume(&mut self, count: usize) {
+        self.output.drain(..count);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.finished && self.output.is_empty()
+    }
+}
+
+impl Write for ChunkedResponse {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.finished {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+
+        self.write_chunk(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for HttpResponse<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Status: {:?}, ProtocolVersion: {:?}, {}", self.status, self.version, std::str::from_utf8(self.body.slice()).unwrap())
     }
 }
+
+fn write_field(out: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    out.extend_from_slice(name);
+    out.extend_from_slice(b": ");
+    out.extend_from_slice(value);
+    out.extend_from_slice(b"\r\n");
+}
+
+fn fields(lines: &[u8]) -> impl Iterator<Item = http::Header<'_>> {
+    lines.split(|&b| b == b'\n').map(|l| l.strip_suffix(b"\r").unwrap_or(l)).filter_map(|l| http::Header::from_bytes(l).ok())
+}
+
+fn compress<'a>(bytes: &[u8], encoding: http::Encoding, allocator: &'a alloc::Allocator<'a>) -> Result<collection::Array<'a, u8>, err::Error> {
+    let mut out = collection::Array::new(bytes.len(), allocator)?;
+
+    match encoding {
+        http::Encoding::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(&mut out, flate2::Compression::default());
+            encoder.write_all(bytes).map_err(|_| err::Error::OutOfBounds)?;
+            encoder.finish().map_err(|_| err::Error::OutOfBounds)?;
+        }
+        http::Encoding::Deflate => {
+            let mut encoder = flate2::write::ZlibEncoder::new(&mut out, flate2::Compression::default());
+            encoder.write_all(bytes).map_err(|_| err::Error::OutOfBounds)?;
+            encoder.finish().map_err(|_| err::Error::OutOfBounds)?;
+        }
+        http::Encoding::Brotli => {
+            let params = brotli::enc::BrotliEncoderParams { quality: 5, lgwin: 22, ..Default::default() };
+            brotli::enc::BrotliCompress(&mut &bytes[..], &mut out, &params).map_err(|_| err::Error::OutOfBounds)?;
+        }
+        http::Encoding::Identity => out.append_slice(bytes)?,
+    }
+
+    Ok(out)
+}
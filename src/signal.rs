@@ -0,0 +1,82 @@
+use crate::{err, net};
+
+static HANGUP: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+static TERMINATE: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+static INTERRUPT: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+static USER1: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+static USER2: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+static NOTIFY: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Signal {
+    Hangup,
+    Terminate,
+    Interrupt,
+    User1,
+    User2,
+}
+
+impl Signal {
+    fn flag(&self) -> &'static std::sync::atomic::AtomicBool {
+        match self {
+            Signal::Hangup => &HANGUP,
+            Signal::Terminate => &TERMINATE,
+            Signal::Interrupt => &INTERRUPT,
+            Signal::User1 => &USER1,
+            Signal::User2 => &USER2,
+        }
+    }
+
+    fn number(&self) -> libc::c_int {
+        match self {
+            Signal::Hangup => libc::SIGHUP,
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Interrupt => libc::SIGINT,
+            Signal::User1 => libc::SIGUSR1,
+            Signal::User2 => libc::SIGUSR2,
+        }
+    }
+}
+
+pub fn install(signals: &[Signal]) -> Result<(), err::Error> {
+    for signal in signals {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            if libc::sigaction(signal.number(), &action, std::ptr::null_mut()) != 0 {
+                return Err(err::Error::Signal);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn notify(fd: std::os::fd::RawFd) {
+    NOTIFY.store(fd, std::sync::atomic::Ordering::SeqCst);
+}
+
+pub fn take(signal: Signal) -> bool {
+    signal.flag().swap(false, std::sync::atomic::Ordering::SeqCst)
+}
+
+extern "C" fn handler(number: libc::c_int) {
+    let signal = match number {
+        libc::SIGHUP => Signal::Hangup,
+        libc::SIGTERM => Signal::Terminate,
+        libc::SIGINT => Signal::Interrupt,
+        libc::SIGUSR1 => Signal::User1,
+        libc::SIGUSR2 => Signal::User2,
+        _ => return,
+    };
+
+    signal.flag().store(true, std::sync::atomic::Ordering::SeqCst);
+
+    let fd = NOTIFY.load(std::sync::atomic::Ordering::SeqCst);
+    if fd >= 0 {
+        net::notify(fd);
+    }
+}
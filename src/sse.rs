@@ -0,0 +1,248 @@
+use crate::err;
+
+pub const KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(15);
+pub const MAX_BACKLOG: usize = 64 * 1024;
+
+pub struct Event {
+    id: u64,
+    name: Option<String>,
+    data: String,
+}
+
+pub struct Channel {
+    path: Vec<u8>,
+    history: usize,
+    state: std::sync::Mutex<State>,
+}
+
+struct State {
+    last_id: u64,
+    last_subscriber: u64,
+    events: std::collections::VecDeque<Event>,
+    waiters: Vec<(u64, std::task::Waker)>,
+}
+
+pub struct Subscription {
+    channel: std::sync::Arc<Channel>,
+    id: u64,
+    cursor: u64,
+    output: Vec<u8>,
+    last_write: std::time::Instant,
+}
+
+impl Event {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(b"id: ");
+        out.extend_from_slice(self.id.to_string().as_bytes());
+        out.push(b'\n');
+
+        if let Some(name) = &self.name {
+            out.extend_from_slice(b"event: ");
+            out.extend_from_slice(name.as_bytes());
+            out.push(b'\n');
+        }
+
+        for line in self.data.split('\n') {
+            out.extend_from_slice(b"data: ");
+            out.extend_from_slice(line.strip_suffix('\r').unwrap_or(line).as_bytes());
+            out.push(b'\n');
+        }
+
+        out.push(b'\n');
+    }
+}
+
+impl Channel {
+    pub fn new(path: &[u8], history: usize) -> std::sync::Arc<Channel> {
+        std::sync::Arc::new(Channel {
+            path: path.to_vec(),
+            history,
+            state: std::sync::Mutex::new(State {
+                last_id: 0,
+                last_subscriber: 0,
+                events: std::collections::VecDeque::with_capacity(history),
+                waiters: Vec::new(),
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &[u8] {
+        &self.path
+    }
+
+    pub fn publish(&self, name: Option<&str>, data: &str) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.last_id += 1;
+
+        let event = Event {
+            id: state.last_id,
+            name: name.map(|n| n.replace(['\r', '\n'], "")),
+            data: data.to_owned(),
+        };
+
+        if state.events.len() == self.history {
+            state.events.pop_front();
+        }
+
+        if self.history > 0 {
+            state.events.push_back(event);
+        }
+
+        for (_, waker) in state.waiters.drain(..) {
+            waker.wake();
+        }
+
+        state.last_id
+    }
+
+    pub fn subscribe(self: &std::sync::Arc<Channel>, last_event_id: Option<&[u8]>) -> Subscription {
+        let mut state = self.state.lock().unwrap();
+        let last_id = state.last_id;
+        let cursor = last_event_id.and_then(|id| std::str::from_utf8(id).ok()).and_then(|id| id.trim().parse().ok()).filter(|&id| id <= last_id);
+
+        state.last_subscriber += 1;
+
+        Subscription {
+            channel: self.clone(),
+            id: state.last_subscriber,
+            cursor: cursor.unwrap_or(last_id),
+            output: Vec::new(),
+            last_write: std::time::Instant::now(),
+        }
+    }
+}
+
+impl Subscription {
+    pub fn poll(&mut self, waker: &std::task::Waker) -> Result<(), err::Error> {
+        let mut state = self.channel.state.lock().unwrap();
+
+        for event in state.events.iter().filter(|e| e.id > self.cursor) {
+            event.encode(&mut self.output);
+        }
+
+        if state.last_id > self.cursor {
+            self.cursor = state.last_id;
+            self.last_write = std::time::Instant::now();
+        } else if self.last_write.elapsed() >= KEEP_ALIVE {
+            self.output.extend_from_slice(b": keep-alive\n\n");
+            self.last_write = std::time::Instant::now();
+        }
+
+        match state.waiters.iter_mut().find(|(id, _)| *id == self.id) {
+            Some((_, registered)) => registered.clone_from(waker),
+            None => state.waiters.push((self.id, waker.clone())),
+        }
+
+        if self.output.len() > MAX_BACKLOG {
+            return Err(err::Error::OutOfBounds);
+        }
+
+        Ok(())
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn consume(&mut self, count: usize) {
+        self.output.drain(..count);
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.channel.state.lock() {
+            state.waiters.retain(|(id, _)| *id != self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter(std::sync::atomic::AtomicUsize);
+
+    impl std::task::Wake for Counter {
+        fn wake(self: std::sync::Arc<Counter>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &std::sync::Arc<Counter>) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    fn counter() -> (std::sync::Arc<Counter>, std::task::Waker) {
+        let counter = std::sync::Arc::new(Counter(std::sync::atomic::AtomicUsize::new(0)));
+
+        (counter.clone(), std::task::Waker::from(counter))
+    }
+
+    fn woken(counter: &Counter) -> usize {
+        counter.0.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    #[test]
+    fn delivers_events_after_the_cursor() {
+        let channel = Channel::new(b"/events", 4);
+        channel.publish(None, "first");
+
+        let (_, waker) = counter();
+        let mut subscription = channel.subscribe(Some(b"0"));
+        subscription.poll(&waker).unwrap();
+        assert_eq!(subscription.output(), b"id: 1\ndata: first\n\n");
+        subscription.consume(subscription.output().len());
+
+        channel.publish(Some("update\r\n"), "a\r\nb");
+        subscription.poll(&waker).unwrap();
+        assert_eq!(subscription.output(), b"id: 2\nevent: update\ndata: a\ndata: b\n\n");
+    }
+
+    #[test]
+    fn dropping_a_subscription_keeps_siblings_sharing_a_waker() {
+        let channel = Channel::new(b"/events", 4);
+        let (counter, waker) = counter();
+
+        let mut first = channel.subscribe(None);
+        let mut second = channel.subscribe(None);
+        first.poll(&waker).unwrap();
+        second.poll(&waker).unwrap();
+        first.poll(&waker).unwrap();
+        assert_eq!(channel.state.lock().unwrap().waiters.len(), 2);
+
+        drop(first);
+        assert_eq!(channel.state.lock().unwrap().waiters.len(), 1);
+
+        channel.publish(None, "event");
+        assert_eq!(woken(&counter), 1);
+
+        second.poll(&waker).unwrap();
+        assert!(second.output().starts_with(b"id: 1\n"));
+    }
+
+    #[test]
+    fn fails_once_the_backlog_exceeds_the_limit() {
+        let channel = Channel::new(b"/events", 1);
+        let (_, waker) = counter();
+        let mut subscription = channel.subscribe(None);
+        let data = "x".repeat(1024);
+
+        loop {
+            channel.publish(None, &data);
+
+            match subscription.poll(&waker) {
+                Ok(()) => assert!(subscription.output().len() <= MAX_BACKLOG),
+                Err(e) => {
+                    assert!(matches!(e, err::Error::OutOfBounds));
+                    assert!(subscription.output().len() > MAX_BACKLOG);
+                    break;
+                }
+            }
+        }
+
+        subscription.consume(subscription.output().len());
+        channel.publish(None, &data);
+        assert!(subscription.poll(&waker).is_ok());
+    }
+}
@@ -0,0 +1,685 @@
+use crate::{alloc, collection, err};
+
+const MAX_DEPTH: usize = 8;
+
+pub struct Engine<'a> {
+    templates: collection::Array<'a, Template<'a>>,
+}
+
+pub struct Template<'a> {
+    name: collection::Array<'a, u8>,
+    source: collection::Array<'a, u8>,
+    nodes: collection::Array<'a, Node<'a>>,
+    parent: Option<Span>,
+}
+
+#[derive(Clone, Copy)]
+pub enum Value<'a> {
+    Text(&'a [u8]),
+    Number(usize),
+    Bool(bool),
+    List(&'a [Value<'a>]),
+    Map(&'a Context<'a>),
+}
+
+pub struct Context<'a> {
+    entries: collection::Array<'a, (&'a [u8], Value<'a>)>,
+}
+
+#[derive(Clone, Copy)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+enum Node<'a> {
+    Text(Span),
+    Variable { path: Span, escape: bool },
+    If { condition: Span, negate: bool, then: collection::Array<'a, Node<'a>>, otherwise: collection::Array<'a, Node<'a>> },
+    For { item: Span, list: Span, body: collection::Array<'a, Node<'a>> },
+    Include(Span),
+    Block { name: Span, body: collection::Array<'a, Node<'a>> },
+}
+
+#[derive(Clone, Copy)]
+enum Token {
+    Text(Span),
+    Expression(Span),
+    Tag(Span),
+}
+
+enum Terminator {
+    Eof,
+    Else,
+    End,
+}
+
+struct Scope<'s, 'a> {
+    context: &'s Context<'a>,
+    binding: Option<(&'s [u8], Value<'a>)>,
+    parent: Option<&'s Scope<'s, 'a>>,
+}
+
+struct Parser<'t> {
+    source: &'t [u8],
+    tokens: &'t [Token],
+    position: usize,
+    parent: Option<Span>,
+}
+
+impl<'a> Engine<'a> {
+    pub fn new(capacity: usize, allocator: &'a alloc::Allocator<'a>) -> Result<Engine<'a>, err::Error> {
+        Ok(Engine {
+            templates: collection::Array::new(capacity, allocator)?,
+        })
+    }
+
+    pub fn compile(&mut self, name: &[u8], source: collection::Array<'a, u8>, allocator: &'a alloc::Allocator<'a>) -> Result<(), err::Error> {
+        let template = Template::compile(name, source, allocator)?;
+        self.templates.push(template)
+    }
+
+    pub fn get(&self, name: &[u8]) -> Option<&Template<'a>> {
+        self.templates.slice().iter().find(|t| t.name.slice() == name)
+    }
+
+    pub fn render(&self, name: &[u8], context: &Context, out: &mut collection::Array<u8>) -> Result<(), err::Error> {
+        let first = self.get(name).ok_or(err::Error::Template)?;
+        let mut chain = [first; MAX_DEPTH];
+        let mut len = 1;
+
+        while let Some(parent) = chain[len - 1].parent {
+            if len == MAX_DEPTH {
+                return Err(err::Error::Template);
+            }
+
+            chain[len] = self.get(chain[len - 1].text(parent)).ok_or(err::Error::Template)?;
+            len += 1;
+        }
+
+        let scope = Scope { context, binding: None, parent: None };
+        let root = chain[len - 1];
+
+        self.render_nodes(root, root.nodes.slice(), &chain[..len], &scope, out, 0)
+    }
+
+    pub fn render_block(&self, name: &[u8], block: &[u8], context: &Context, out: &mut collection::Array<u8>) -> Result<(), err::Error> {
+        let template = self.get(name).ok_or(err::Error::Template)?;
+        let body = template.find_block(template.nodes.slice(), block).ok_or(err::Error::Template)?;
+        let scope = Scope { context, binding: None, parent: None };
+
+        self.render_nodes(template, body, &[template], &scope, out, 0)
+    }
+
+    fn render_nodes(&self, template: &Template, nodes: &[Node], chain: &[&Template], scope: &Scope, out: &mut collection::Array<u8>, depth: usize) -> Result<(), err::Error> {
+        if depth > MAX_DEPTH {
+            return Err(err::Error::Template);
+        }
+
+        for node in nodes {
+            match node {
+                Node::Text(span) => out.append_slice(template.text(*span))?,
+                Node::Variable { path, escape } => {
+                    if let Some(value) = scope.lookup(template.text(*path)) {
+                        write_value(value, *escape, out)?;
+                    }
+                }
+                Node::If { condition, negate, then, otherwise } => {
+                    let truthy = scope.lookup(template.text(*condition)).map(|v| v.truthy()).unwrap_or(false);
+                    let branch = if truthy != *negate { then } else { otherwise };
+
+                    self.render_nodes(template, branch.slice(), chain, scope, out, depth)?;
+                }
+                Node::For { item, list, body } => {
+                    let Some(Value::List(values)) = scope.lookup(template.text(*list)) else {
+                        continue;
+                    };
+
+                    for value in values {
+                        let inner = Scope { context: scope.context, binding: Some((template.text(*item), *value)), parent: Some(scope) };
+                        self.render_nodes(template, body.slice(), chain, &inner, out, depth)?;
+                    }
+                }
+                Node::Include(name) => {
+                    let included = self.get(template.text(*name)).ok_or(err::Error::Template)?;
+                    self.render_nodes(included, included.nodes.slice(), &[included], scope, out, depth + 1)?;
+                }
+                Node::Block { name, body } => {
+                    let name = template.text(*name);
+                    let mut found = false;
+
+                    for t in chain {
+                        if let Some(nodes) = t.find_block(t.nodes.slice(), name) {
+                            self.render_nodes(t, nodes, chain, scope, out, depth + 1)?;
+                            found = true;
+                            break;
+                        }
+                    }
+
+                    if !found {
+                        self.render_nodes(template, body.slice(), chain, scope, out, depth + 1)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Template<'a> {
+    fn compile(name: &[u8], source: collection::Array<'a, u8>, allocator: &'a alloc::Allocator<'a>) -> Result<Template<'a>, err::Error> {
+        let tokens = tokenize(source.slice(), allocator)?;
+
+        let mut parser = Parser {
+            source: source.slice(),
+            tokens: tokens.slice(),
+            position: 0,
+            parent: None,
+        };
+
+        let (nodes, terminator) = parser.parse(allocator)?;
+
+        if !matches!(terminator, Terminator::Eof) {
+            return Err(err::Error::Template);
+        }
+
+        let parent = parser.parent;
+
+        let mut template_name = collection::Array::new(name.len(), allocator)?;
+        template_name.copy(name)?;
+
+        Ok(Template {
+            name: template_name,
+            source,
+            nodes,
+            parent,
+        })
+    }
+
+    fn text(&self, span: Span) -> &[u8] {
+        &self.source.slice()[span.start..span.end]
+    }
+
+    fn find_block<'t>(&'t self, nodes: &'t [Node<'a>], name: &[u8]) -> Option<&'t [Node<'a>]> {
+        for node in nodes {
+            match node {
+                Node::Block { name: n, body } => {
+                    if self.text(*n) == name {
+                        return Some(body.slice());
+                    }
+
+                    if let Some(found) = self.find_block(body.slice(), name) {
+                        return Some(found);
+                    }
+                }
+                Node::If { then, otherwise, .. } => {
+                    if let Some(found) = self.find_block(then.slice(), name) {
+                        return Some(found);
+                    }
+
+                    if let Some(found) = self.find_block(otherwise.slice(), name) {
+                        return Some(found);
+                    }
+                }
+                Node::For { body, .. } => {
+                    if let Some(found) = self.find_block(body.slice(), name) {
+                        return Some(found);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+impl<'t> Parser<'t> {
+    fn parse<'a>(&mut self, allocator: &'a alloc::Allocator<'a>) -> Result<(collection::Array<'a, Node<'a>>, Terminator), err::Error> {
+        let mut nodes = collection::Array::new(self.count_children(), allocator)?;
+
+        while self.position < self.tokens.len() {
+            let token = self.tokens[self.position];
+            self.position += 1;
+
+            match token {
+                Token::Text(span) => nodes.push(Node::Text(span))?,
+                Token::Expression(span) => {
+                    let mut words = Words::new(self.source, span);
+                    let path = words.next().ok_or(err::Error::Template)?;
+
+                    let escape = match (words.next(), words.next()) {
+                        (None, _) => true,
+                        (Some(pipe), Some(filter)) if self.text(pipe) == b"|" && self.text(filter) == b"raw" => false,
+                        _ => return Err(err::Error::Template),
+                    };
+
+                    nodes.push(Node::Variable { path, escape })?;
+                }
+                Token::Tag(span) => {
+                    let mut words = Words::new(self.source, span);
+                    let keyword = words.next().ok_or(err::Error::Template)?;
+
+                    match self.text(keyword) {
+                        b"end" => return Ok((nodes, Terminator::End)),
+                        b"else" => return Ok((nodes, Terminator::Else)),
+                        b"if" => {
+                            let mut condition = words.next().ok_or(err::Error::Template)?;
+                            let mut negate = false;
+
+                            if self.text(condition) == b"not" {
+                                negate = true;
+                                condition = words.next().ok_or(err::Error::Template)?;
+                            }
+
+                            let (then, terminator) = self.parse(allocator)?;
+                            let otherwise = match terminator {
+                                Terminator::Else => {
+                                    let (otherwise, terminator) = self.parse(allocator)?;
+                                    if !matches!(terminator, Terminator::End) {
+                                        return Err(err::Error::Template);
+                                    }
+
+                                    otherwise
+                                }
+                                Terminator::End => collection::Array::new(0, allocator)?,
+                                Terminator::Eof => return Err(err::Error::Template),
+                            };
+
+                            nodes.push(Node::If { condition, negate, then, otherwise })?;
+                        }
+                        b"for" => {
+                            let item = words.next().ok_or(err::Error::Template)?;
+                            let keyword = words.next().ok_or(err::Error::Template)?;
+                            let list = words.next().ok_or(err::Error::Template)?;
+
+                            if self.text(keyword) != b"in" {
+                                return Err(err::Error::Template);
+                            }
+
+                            let body = self.parse_body(allocator)?;
+                            nodes.push(Node::For { item, list, body })?;
+                        }
+                        b"block" => {
+                            let name = words.next().ok_or(err::Error::Template)?;
+                            let body = self.parse_body(allocator)?;
+
+                            nodes.push(Node::Block { name, body })?;
+                        }
+                        b"include" => {
+                            let name = quoted(self.source, words.next())?;
+                            nodes.push(Node::Include(name))?;
+                        }
+                        b"extends" => {
+                            if self.parent.is_some() {
+                                return Err(err::Error::Template);
+                            }
+
+                            self.parent = Some(quoted(self.source, words.next())?);
+                        }
+                        _ => return Err(err::Error::Template),
+                    }
+                }
+            }
+        }
+
+        Ok((nodes, Terminator::Eof))
+    }
+
+    fn parse_body<'a>(&mut self, allocator: &'a alloc::Allocator<'a>) -> Result<collection::Array<'a, Node<'a>>, err::Error> {
+        let (body, terminator) = self.parse(allocator)?;
+
+        if matches!(terminator, Terminator::End) {
+            Ok(body)
+        } else {
+            Err(err::Error::Template)
+        }
+    }
+
+    fn count_children(&self) -> usize {
+        let mut depth = 0;
+        let mut count = 0;
+
+        for token in &self.tokens[self.position..] {
+            let keyword = match token {
+                Token::Tag(span) => Words::new(self.source, *span).next().map(|k| self.text(k)),
+                _ => None,
+            };
+
+            match keyword {
+                Some(b"if") | Some(b"for") | Some(b"block") => {
+                    if depth == 0 {
+                        count += 1;
+                    }
+
+                    depth += 1;
+                }
+                Some(b"end") => {
+                    if depth == 0 {
+                        break;
+                    }
+
+                    depth -= 1;
+                }
+                Some(b"else") if depth == 0 => break,
+                _ => {
+                    if depth == 0 {
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        count
+    }
+
+    fn text(&self, span: Span) -> &'t [u8] {
+        &self.source[span.start..span.end]
+    }
+}
+
+impl<'a> Value<'a> {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Text(t) => !t.is_empty(),
+            Value::Number(n) => *n != 0,
+            Value::Bool(b) => *b,
+            Value::List(l) => !l.is_empty(),
+            Value::Map(_) => true,
+        }
+    }
+}
+
+impl<'a> Context<'a> {
+    pub fn new(capacity: usize, allocator: &'a alloc::Allocator<'a>) -> Result<Context<'a>, err::Error> {
+        Ok(Context {
+            entries: collection::Array::new(capacity, allocator)?,
+        })
+    }
+
+    pub fn insert(&mut self, key: &'a [u8], value: Value<'a>) -> Result<(), err::Error> {
+        for entry in self.entries.slice_mut() {
+            if entry.0 == key {
+                entry.1 = value;
+                return Ok(());
+            }
+        }
+
+        self.entries.push((key, value))
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Value<'a>> {
+        self.entries.slice().iter().find(|e| e.0 == key).map(|e| e.1)
+    }
+}
+
+impl<'s, 'a> Scope<'s, 'a> {
+    fn lookup(&self, path: &[u8]) -> Option<Value<'a>> {
+        let mut segments = path.split(|&b| b == b'.');
+        let mut value = self.find(segments.next()?)?;
+
+        for segment in segments {
+            value = match value {
+                Value::Map(map) => map.get(segment)?,
+                _ => return None,
+            };
+        }
+
+        Some(value)
+    }
+
+    fn find(&self, name: &[u8]) -> Option<Value<'a>> {
+        if let Some((binding, value)) = self.binding {
+            if binding == name {
+                return Some(value);
+            }
+        }
+
+        match self.parent {
+            Some(parent) => parent.find(name),
+            None => self.context.get(name),
+        }
+    }
+}
+
+struct Words<'t> {
+    source: &'t [u8],
+    position: usize,
+    end: usize,
+}
+
+impl<'t> Words<'t> {
+    fn new(source: &'t [u8], span: Span) -> Words<'t> {
+        Words { source, position: span.start, end: span.end }
+    }
+}
+
+impl Iterator for Words<'_> {
+    type Item = Span;
+
+    fn next(&mut self) -> Option<Span> {
+        while self.position < self.end && self.source[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+
+        if self.position == self.end {
+            return None;
+        }
+
+        let start = self.position;
+        while self.position < self.end && !self.source[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+
+        Some(Span { start, end: self.position })
+    }
+}
+
+fn tokenize<'a>(source: &[u8], allocator: &'a alloc::Allocator<'a>) -> Result<collection::Array<'a, Token>, err::Error> {
+    let tags = source.windows(2).filter(|w| w == b"{{" || w == b"{%").count();
+    let mut tokens = collection::Array::new(2 * tags + 1, allocator)?;
+
+    let mut position = 0;
+    let mut text_start = 0;
+
+    while position + 1 < source.len() {
+        let close: &[u8] = match &source[position..position + 2] {
+            b"{{" => b"}}",
+            b"{%" => b"%}",
+            _ => {
+                position += 1;
+                continue;
+            }
+        };
+
+        if text_start < position {
+            tokens.push(Token::Text(Span { start: text_start, end: position }))?;
+        }
+
+        let inner = position + 2;
+        let Some(offset) = source[inner..].windows(2).position(|w| w == close) else {
+            return Err(err::Error::Template);
+        };
+
+        let span = Span { start: inner, end: inner + offset };
+        tokens.push(if close == b"}}" { Token::Expression(span) } else { Token::Tag(span) })?;
+
+        position = inner + offset + 2;
+        text_start = position;
+    }
+
+    if text_start < source.len() {
+        tokens.push(Token::Text(Span { start: text_start, end: source.len() }))?;
+    }
+
+    Ok(tokens)
+}
+
+fn quoted(source: &[u8], word: Option<Span>) -> Result<Span, err::Error> {
+    let span = word.ok_or(err::Error::Template)?;
+
+    if span.end - span.start < 2 || source[span.start] != b'"' || source[span.end - 1] != b'"' {
+        return Err(err::Error::Template);
+    }
+
+    Ok(Span { start: span.start + 1, end: span.end - 1 })
+}
+
+fn write_value(value: Value, escape: bool, out: &mut collection::Array<u8>) -> Result<(), err::Error> {
+    match value {
+        Value::Text(text) => {
+            if escape {
+                write_escaped(text, out)
+            } else {
+                out.append_slice(text)
+            }
+        }
+        Value::Number(n) => out.parse(n),
+        Value::Bool(true) => out.append_slice(b"true"),
+        Value::Bool(false) => out.append_slice(b"false"),
+        Value::List(_) | Value::Map(_) => Err(err::Error::Template),
+    }
+}
+
+fn write_escaped(text: &[u8], out: &mut collection::Array<u8>) -> Result<(), err::Error> {
+    for &b in text {
+        match b {
+            b'&' => out.append_slice(b"&amp;")?,
+            b'<' => out.append_slice(b"&lt;")?,
+            b'>' => out.append_slice(b"&gt;")?,
+            b'"' => out.append_slice(b"&quot;")?,
+            b'\'' => out.append_slice(b"&#39;")?,
+            _ => out.push(b)?,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile<'a>(engine: &mut Engine<'a>, name: &str, source: &str, allocator: &'a alloc::Allocator<'a>) -> Result<(), err::Error> {
+        let mut bytes = collection::Array::new(source.len(), allocator)?;
+        bytes.append_slice(source.as_bytes())?;
+
+        engine.compile(name.as_bytes(), bytes, allocator)
+    }
+
+    fn engine<'a>(templates: &[(&str, &str)], allocator: &'a alloc::Allocator<'a>) -> Engine<'a> {
+        let mut engine = Engine::new(templates.len(), allocator).unwrap();
+
+        for (name, source) in templates {
+            compile(&mut engine, name, source, allocator).unwrap();
+        }
+
+        engine
+    }
+
+    fn render(engine: &Engine, name: &str, context: &Context, allocator: &alloc::Allocator) -> Result<std::string::String, err::Error> {
+        let mut out = collection::Array::new(256, allocator)?;
+        engine.render(name.as_bytes(), context, &mut out)?;
+
+        Ok(std::string::String::from_utf8(out.slice().to_vec()).unwrap())
+    }
+
+    #[test]
+    fn escapes_html_unless_raw() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let engine = engine(&[("page", "{{ value }}|{{ value | raw }}")], &allocator);
+        let mut context = Context::new(1, &allocator).unwrap();
+        context.insert(b"value", Value::Text(b"<a href=\"x\">'&'</a>")).unwrap();
+
+        assert_eq!(render(&engine, "page", &context, &allocator).unwrap(), "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;|<a href=\"x\">'&'</a>");
+    }
+
+    #[test]
+    fn renders_conditions_and_loops() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let engine = engine(&[("page", "{% if admin %}admin{% else %}user{% end %}{% if not items %}none{% end %}{% for item in items %}[{{ item }}]{% end %}")], &allocator);
+        let items = [Value::Text(b"a"), Value::Number(2)];
+
+        let mut context = Context::new(2, &allocator).unwrap();
+        context.insert(b"admin", Value::Bool(true)).unwrap();
+        context.insert(b"items", Value::List(&items)).unwrap();
+
+        assert_eq!(render(&engine, "page", &context, &allocator).unwrap(), "admin[a][2]");
+
+        context.insert(b"admin", Value::Bool(false)).unwrap();
+        context.insert(b"items", Value::List(&[])).unwrap();
+
+        assert_eq!(render(&engine, "page", &context, &allocator).unwrap(), "usernone");
+    }
+
+    #[test]
+    fn extends_overrides_blocks_and_keeps_defaults() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let engine = engine(&[
+            ("layout", "<title>{% block title %}site{% end %}</title>{% block content %}empty{% end %}"),
+            ("section", "{% extends \"layout\" %}{% block content %}section{% end %}"),
+            ("page", "{% extends \"section\" %}{% block title %}page{% end %}"),
+        ], &allocator);
+        let context = Context::new(0, &allocator).unwrap();
+
+        assert_eq!(render(&engine, "layout", &context, &allocator).unwrap(), "<title>site</title>empty");
+        assert_eq!(render(&engine, "section", &context, &allocator).unwrap(), "<title>site</title>section");
+        assert_eq!(render(&engine, "page", &context, &allocator).unwrap(), "<title>page</title>section");
+    }
+
+    #[test]
+    fn renders_a_single_block() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let engine = engine(&[("page", "before{% block content %}<p>{{ name }}</p>{% end %}after")], &allocator);
+        let mut context = Context::new(1, &allocator).unwrap();
+        context.insert(b"name", Value::Text(b"world")).unwrap();
+
+        let mut out = collection::Array::new(64, &allocator).unwrap();
+        engine.render_block(b"page", b"content", &context, &mut out).unwrap();
+
+        assert_eq!(out.slice(), b"<p>world</p>");
+    }
+
+    #[test]
+    fn limits_include_recursion() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let engine = engine(&[
+            ("loop", "x{% include \"loop\" %}"),
+            ("outer", "<{% include \"inner\" %}>"),
+            ("inner", "inner"),
+            ("first", "{% extends \"second\" %}"),
+            ("second", "{% extends \"first\" %}"),
+        ], &allocator);
+        let context = Context::new(0, &allocator).unwrap();
+
+        assert_eq!(render(&engine, "outer", &context, &allocator).unwrap(), "<inner>");
+        assert!(matches!(render(&engine, "loop", &context, &allocator), Err(err::Error::Template)));
+        assert!(matches!(render(&engine, "first", &context, &allocator), Err(err::Error::Template)));
+        assert!(matches!(render(&engine, "missing", &context, &allocator), Err(err::Error::Template)));
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        let allocator = alloc::Allocator::new(1 << 16);
+        let mut engine = Engine::new(16, &allocator).unwrap();
+
+        for source in [
+            "{{ name",
+            "{% if name %}",
+            "{% if name %}{% else %}",
+            "{% for item items %}{% end %}",
+            "{% for item of items %}{% end %}",
+            "{% block content %}",
+            "{% end %}",
+            "{% else %}",
+            "{{ name | upper }}",
+            "{{ }}",
+            "{% unknown %}",
+            "{% include layout %}",
+            "{% extends \"a\" %}{% extends \"b\" %}",
+        ] {
+            assert!(matches!(compile(&mut engine, "bad", source, &allocator), Err(err::Error::Template)), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{alloc, collection, err};
+
+const NONE: usize = usize::MAX;
+
+pub struct Wheel<'a> {
+    heads: collection::Array<'a, usize>,
+    entries: collection::Array<'a, Entry>,
+    pending: usize,
+    resolution: std::time::Duration,
+    origin: std::time::Instant,
+    tick: u64,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    deadline: Option<std::time::Instant>,
+    list: usize,
+    prev: usize,
+    next: usize,
+}
+
+impl<'a> Wheel<'a> {
+    pub fn new(capacity: usize, slots: usize, resolution: std::time::Duration, allocator: &'a alloc::Allocator<'a>) -> Result<Wheel<'a>, err::Error> {
+        let slots = slots.max(1);
+        let mut heads = collection::Array::new(slots + 1, allocator)?;
+        let mut entries = collection::Array::new(capacity, allocator)?;
+
+        heads.resize(slots + 1, NONE)?;
+        entries.resize(capacity, Entry { deadline: None, list: NONE, prev: NONE, next: NONE })?;
+
+        Ok(Wheel {
+            heads,
+            entries,
+            pending: 0,
+            resolution,
+            origin: std::time::Instant::now(),
+            tick: 0,
+        })
+    }
+
+    pub fn schedule(&mut self, id: usize, deadline: std::time::Instant) {
+        if id >= self.entries.len() {
+            return;
+        }
+
+        self.unlink(id);
+        self.entries[id].deadline = Some(deadline);
+        self.insert(id, deadline);
+    }
+
+    pub fn cancel(&mut self, id: usize) {
+        if id < self.entries.len() {
+            self.unlink(id);
+            self.entries[id].deadline = None;
+        }
+    }
+
+    pub fn take_expired(&mut self, id: usize) -> bool {
+        if self.entries.get(id).is_none_or(|e| e.list != self.expired_list()) {
+            return false;
+        }
+
+        self.unlink(id);
+        true
+    }
+
+    pub fn advance(&mut self, now: std::time::Instant) {
+        let target = self.tick_of(now);
+
+        if target <= self.tick {
+            return;
+        }
+
+        let slots = self.slots() as u64;
+        let steps = (target - self.tick).min(slots);
+
+        for tick in self.tick..self.tick + steps {
+            let index = (tick % slots) as usize;
+            let mut id = self.heads[index];
+
+            while id != NONE {
+                let next = self.entries[id].next;
+                self.unlink(id);
+
+                match self.entries[id].deadline {
+                    Some(deadline) if deadline <= now => {
+                        self.entries[id].deadline = None;
+                        self.link(id, self.expired_list());
+                    }
+                    Some(deadline) => self.insert(id, deadline),
+                    None => {}
+                }
+
+                id = next;
+            }
+        }
+
+        self.tick = target;
+    }
+
+    pub fn first_expired(&self) -> Option<usize> {
+        Some(self.heads[self.expired_list()]).filter(|id| *id != NONE)
+    }
+
+    pub fn next_expired(&self, id: usize) -> Option<usize> {
+        self.entries.get(id).filter(|e| e.list == self.expired_list()).map(|e| e.next).filter(|id| *id != NONE)
+    }
+
+    pub fn next_deadline(&self) -> Option<std::time::Instant> {
+        if self.pending == 0 {
+            return None;
+        }
+
+        let slots = self.slots() as u64;
+        let tick = (self.tick..self.tick + slots).find(|t| self.heads[(t % slots) as usize] != NONE)?;
+
+        Some(self.origin + std::time::Duration::from_nanos((self.resolution.as_nanos() as u64).saturating_mul(tick + 1)))
+    }
+
+    fn insert(&mut self, id: usize, deadline: std::time::Instant) {
+        let tick = self.tick_of(deadline).max(self.tick);
+        let index = (tick % self.slots() as u64) as usize;
+
+        self.link(id, index);
+    }
+
+    fn link(&mut self, id: usize, list: usize) {
+        let head = self.heads[list];
+
+        if head != NONE {
+            self.entries[head].prev = id;
+        }
+
+        self.entries[id] = Entry { deadline: self.entries[id].deadline, list, prev: NONE, next: head };
+        self.heads[list] = id;
+
+        if list != self.expired_list() {
+            self.pending += 1;
+        }
+    }
+
+    fn unlink(&mut self, id: usize) {
+        let Entry { list, prev, next, .. } = self.entries[id];
+
+        if list == NONE {
+            return;
+        }
+
+        if prev == NONE {
+            self.heads[list] = next;
+        } else {
+            self.entries[prev].next = next;
+        }
+
+        if next != NONE {
+            self.entries[next].prev = prev;
+        }
+
+        if list != self.expired_list() {
+            self.pending -= 1;
+        }
+
+        let entry = &mut self.entries[id];
+        entry.list = NONE;
+        entry.prev = NONE;
+        entry.next = NONE;
+    }
+
+    fn slots(&self) -> usize {
+        self.heads.len() - 1
+    }
+
+    fn expired_list(&self) -> usize {
+        self.heads.len() - 1
+    }
+
+    fn tick_of(&self, instant: std::time::Instant) -> u64 {
+        (instant.saturating_duration_since(self.origin).as_nanos() / self.resolution.as_nanos().max(1)) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expired(wheel: &Wheel) -> std::collections::BTreeSet<usize> {
+        std::iter::successors(wheel.first_expired(), |id| wheel.next_expired(*id)).collect()
+    }
+
+    #[test]
+    fn expires_deadlines_once_they_pass() {
+        let allocator = alloc::Allocator::new(1 << 14);
+        let mut wheel = Wheel::new(8, 16, std::time::Duration::from_millis(10), &allocator).unwrap();
+        let now = wheel.origin;
+
+        wheel.schedule(1, now + std::time::Duration::from_millis(25));
+        wheel.schedule(2, now + std::time::Duration::from_millis(55));
+        assert_eq!(wheel.next_deadline(), Some(now + std::time::Duration::from_millis(30)));
+
+        wheel.advance(now + std::time::Duration::from_millis(30));
+        assert_eq!(expired(&wheel), [1].into());
+
+        assert!(wheel.take_expired(1));
+        assert!(!wheel.take_expired(1));
+        assert!(!wheel.take_expired(2));
+        assert_eq!(wheel.first_expired(), None);
+
+        wheel.advance(now + std::time::Duration::from_millis(60));
+        assert_eq!(expired(&wheel), [2].into());
+        assert_eq!(wheel.next_deadline(), None);
+    }
+
+    #[test]
+    fn rescheduling_and_cancelling_replace_the_deadline() {
+        let allocator = alloc::Allocator::new(1 << 14);
+        let mut wheel = Wheel::new(8, 16, std::time::Duration::from_millis(10), &allocator).unwrap();
+        let now = wheel.origin;
+
+        wheel.schedule(1, now + std::time::Duration::from_millis(15));
+        wheel.schedule(1, now + std::time::Duration::from_millis(95));
+        wheel.schedule(2, now + std::time::Duration::from_millis(15));
+        wheel.schedule(3, now + std::time::Duration::from_millis(15));
+        wheel.cancel(2);
+
+        wheel.advance(now + std::time::Duration::from_millis(50));
+        assert_eq!(expired(&wheel), [3].into());
+
+        wheel.schedule(3, now + std::time::Duration::from_millis(75));
+        assert_eq!(wheel.first_expired(), None);
+
+        wheel.advance(now + std::time::Duration::from_millis(100));
+        assert_eq!(expired(&wheel), [1, 3].into());
+
+        wheel.cancel(1);
+        assert_eq!(expired(&wheel), [3].into());
+    }
+
+    #[test]
+    fn keeps_deadlines_beyond_one_rotation() {
+        let allocator = alloc::Allocator::new(1 << 14);
+        let mut wheel = Wheel::new(4, 4, std::time::Duration::from_millis(10), &allocator).unwrap();
+        let now = wheel.origin;
+
+        wheel.schedule(0, now + std::time::Duration::from_millis(105));
+        wheel.advance(now + std::time::Duration::from_millis(50));
+        wheel.advance(now + std::time::Duration::from_millis(90));
+        assert_eq!(wheel.first_expired(), None);
+        assert!(wheel.next_deadline().is_some());
+
+        wheel.advance(now + std::time::Duration::from_millis(110));
+        assert_eq!(expired(&wheel), [0].into());
+    }
+
+    #[test]
+    fn ignores_ids_past_the_capacity() {
+        let allocator = alloc::Allocator::new(1 << 14);
+        let mut wheel = Wheel::new(2, 4, std::time::Duration::from_millis(10), &allocator).unwrap();
+
+        wheel.schedule(2, wheel.origin);
+        wheel.cancel(2);
+        assert!(!wheel.take_expired(2));
+        assert_eq!(wheel.next_deadline(), None);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{config, err, net};
+use rustls::pki_types::pem::PemObject;
+
+pub struct Tls {
+    config: std::sync::Arc<rustls::ServerConfig>,
+    resolver: std::sync::Arc<Resolver>,
+}
+
+#[derive(Debug)]
+struct Resolver {
+    certificates: Vec<config::TlsCertificate>,
+    keys: std::sync::RwLock<Keys>,
+}
+
+#[derive(Debug, Default)]
+struct Keys {
+    named: Vec<(String, std::sync::Arc<rustls::sign::CertifiedKey>)>,
+    fallback: Option<std::sync::Arc<rustls::sign::CertifiedKey>>,
+}
+
+impl Tls {
+    pub fn new(config: &config::ServerConfig) -> Result<Tls, err::Error> {
+        if config.tls_certificates.is_empty() {
+            return Err(err::Error::Config);
+        }
+
+        let resolver = std::sync::Arc::new(Resolver {
+            certificates: config.tls_certificates.clone(),
+            keys: std::sync::RwLock::new(Keys::default()),
+        });
+
+        resolver.reload()?;
+
+        let provider = std::sync::Arc::new(rustls::crypto::ring::default_provider());
+        let mut server = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|_| err::Error::Tls)?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+
+        server.alpn_protocols = config.tls_alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+
+        Ok(Tls {
+            config: std::sync::Arc::new(server),
+            resolver,
+        })
+    }
+
+    pub fn reload(&self) -> Result<(), err::Error> {
+        self.resolver.reload()
+    }
+
+    pub fn accept(&self, stream: std::net::TcpStream) -> Result<net::Stream, err::Error> {
+        let connection = rustls::ServerConnection::new(self.config.clone()).map_err(|_| err::Error::Tls)?;
+
+        Ok(net::Stream::Tls(Box::new(rustls::StreamOwned::new(connection, stream))))
+    }
+}
+
+impl Resolver {
+    fn reload(&self) -> Result<(), err::Error> {
+        let mut keys = Keys::default();
+
+        for certificate in &self.certificates {
+            let key = std::sync::Arc::new(load(certificate)?);
+
+            if certificate.names.is_empty() || keys.fallback.is_none() {
+                keys.fallback = Some(key.clone());
+            }
+
+            for name in &certificate.names {
+                keys.named.push((name.to_ascii_lowercase(), key.clone()));
+            }
+        }
+
+        *self.keys.write().map_err(|_| err::Error::Tls)? = keys;
+
+        Ok(())
+    }
+}
+
+impl rustls::server::ResolvesServerCert for Resolver {
+    fn resolve(&self, hello: rustls::server::ClientHello) -> Option<std::sync::Arc<rustls::sign::CertifiedKey>> {
+        let keys = self.keys.read().ok()?;
+
+        if let Some(name) = hello.server_name() {
+            for (pattern, key) in &keys.named {
+                if matches(pattern, name) {
+                    return Some(key.clone());
+                }
+            }
+        }
+
+        keys.fallback.clone()
+    }
+}
+
+fn load(certificate: &config::TlsCertificate) -> Result<rustls::sign::CertifiedKey, err::Error> {
+    let chain = rustls::pki_types::CertificateDer::pem_file_iter(&certificate.cert)
+        .map_err(|_| err::Error::FileNotFound)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| err::Error::Tls)?;
+
+    let key = rustls::pki_types::PrivateKeyDer::from_pem_file(&certificate.key).map_err(|_| err::Error::FileNotFound)?;
+    let key = rustls::crypto::ring::sign::any_supported_type(&key).map_err(|_| err::Error::Tls)?;
+
+    if chain.is_empty() {
+        return Err(err::Error::Tls);
+    }
+
+    Ok(rustls::sign::CertifiedKey::new(chain, key))
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => name.split_once('.').is_some_and(|(_, rest)| rest.eq_ignore_ascii_case(suffix)),
+        None => pattern.eq_ignore_ascii_case(name),
+    }
+}
@@ -0,0 +1,523 @@
+use crate::{http, request, response, alloc, err};
+
+pub const CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+const GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const DEFLATE_RESPONSE: &[u8] = b"permessage-deflate; server_no_context_takeover; client_no_context_takeover";
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+const MAX_MESSAGE_SIZE: usize = 1 << 20;
+const MAX_CONTROL_SIZE: usize = 125;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+const FIN: u8 = 0x80;
+const RSV1: u8 = 0x40;
+const RSV: u8 = 0x70;
+const MASK: u8 = 0x80;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseCode {
+    Normal = 1000,
+    GoingAway = 1001,
+    Protocol = 1002,
+    Unsupported = 1003,
+    Invalid = 1007,
+    Policy = 1008,
+    TooBig = 1009,
+    Internal = 1011,
+}
+
+pub struct Handshake {
+    accept: [u8; 28],
+    deflate: bool,
+}
+
+pub struct Socket {
+    input: Vec<u8>,
+    output: Vec<u8>,
+    incoming: std::collections::VecDeque<Message>,
+    fragments: Vec<u8>,
+    fragment: Option<(u8, bool)>,
+    deflate: bool,
+    closing: bool,
+    closed: bool,
+}
+
+impl Handshake {
+    pub fn from_request(request: &request::Request) -> Option<Handshake> {
+        let upgrade = request.get(b"Upgrade")?;
+        let connection = request.get(b"Connection")?;
+        let key = request.get(b"Sec-WebSocket-Key")?;
+
+        if request.header().method() != http::Method::Get || !upgrade.eq_ignore_ascii_case(b"websocket") {
+            return None;
+        }
+
+        if !has_token(connection, b"upgrade") || request.get(b"Sec-WebSocket-Version") != Some(b"13") {
+            return None;
+        }
+
+        let deflate = request.get(b"Sec-WebSocket-Extensions").is_some_and(accepts_deflate);
+
+        Some(Handshake {
+            accept: accept_key(key),
+            deflate,
+        })
+    }
+
+    pub fn response<'a>(&self, allocator: &'a alloc::Allocator<'a>) -> Result<response::HttpResponse<'a>, err::Error> {
+        let accept = http::Header::new(b"Sec-WebSocket-Accept", &self.accept);
+        let extensions = http::Header::new(b"Sec-WebSocket-Extensions", DEFLATE_RESPONSE);
+
+        if self.deflate {
+            response::HttpResponse::switching_protocols(b"websocket", &[accept, extensions], allocator)
+        } else {
+            response::HttpResponse::switching_protocols(b"websocket", &[accept], allocator)
+        }
+    }
+
+    pub fn socket(&self, input: Vec<u8>, output: Vec<u8>) -> Socket {
+        Socket::new(self.deflate, input, output)
+    }
+}
+
+impl Socket {
+    pub fn new(deflate: bool, input: Vec<u8>, output: Vec<u8>) -> Socket {
+        Socket {
+            input,
+            output,
+            incoming: std::collections::VecDeque::new(),
+            fragments: Vec::new(),
+            fragment: None,
+            deflate,
+            closing: false,
+            closed: false,
+        }
+    }
+
+    pub fn receive(&mut self, bytes: &[u8]) {
+        self.input.extend_from_slice(bytes);
+    }
+
+    pub fn process(&mut self) {
+        while !self.closed {
+            match self.read_frame() {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(code) => {
+                    self.close(code, b"");
+                    self.closed = true;
+                }
+            }
+        }
+    }
+
+    pub fn recv(&mut self) -> Option<Message> {
+        self.incoming.pop_front()
+    }
+
+    pub fn send(&mut self, message: Message) {
+        if self.closing {
+            return;
+        }
+
+        let (opcode, payload) = match &message {
+            Message::Text(text) => (TEXT, text.as_bytes()),
+            Message::Binary(bytes) => (BINARY, bytes.as_slice()),
+        };
+
+        if self.deflate && payload.len() >= response::COMPRESSION_THRESHOLD {
+            if let Some(compressed) = deflate(payload) {
+                self.frame(RSV1 | opcode, &compressed);
+                return;
+            }
+        }
+
+        self.frame(opcode, payload);
+    }
+
+    pub fn ping(&mut self, payload: &[u8]) {
+        if !self.closing {
+            self.frame(PING, &payload[..payload.len().min(MAX_CONTROL_SIZE)]);
+        }
+    }
+
+    pub fn close(&mut self, code: CloseCode, reason: &[u8]) {
+        if self.closing {
+            return;
+        }
+
+        let reason = &reason[..reason.len().min(MAX_CONTROL_SIZE - 2)];
+        let mut payload = [0; MAX_CONTROL_SIZE];
+        payload[..2].copy_from_slice(&(code as u16).to_be_bytes());
+        payload[2..2 + reason.len()].copy_from_slice(reason);
+
+        self.frame(CLOSE, &payload[..2 + reason.len()]);
+        self.closing = true;
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn consume(&mut self, count: usize) {
+        self.output.drain(..count);
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed && self.output.is_empty()
+    }
+
+    pub fn into_buffers(self) -> (Vec<u8>, Vec<u8>) {
+        (self.input, self.output)
+    }
+
+    fn read_frame(&mut self) -> Result<bool, CloseCode> {
+        if self.input.len() < 2 {
+            return Ok(false);
+        }
+
+        let (first, second) = (self.input[0], self.input[1]);
+        let opcode = first & 0x0f;
+        let fin = first & FIN != 0;
+        let compressed = first & RSV1 != 0;
+
+        if second & MASK == 0 || first & RSV & !RSV1 != 0 {
+            return Err(CloseCode::Protocol);
+        }
+
+        if compressed && (!self.deflate || !matches!(opcode, TEXT | BINARY)) {
+            return Err(CloseCode::Protocol);
+        }
+
+        let (length, offset) = match second & 0x7f {
+            126 if self.input.len() >= 4 => (u16::from_be_bytes([self.input[2], self.input[3]]) as u64, 4),
+            127 if self.input.len() >= 10 => (u64::from_be_bytes(self.input[2..10].try_into().unwrap()), 10),
+            126 | 127 => return Ok(false),
+            length => (length as u64, 2),
+        };
+
+        if length > MAX_MESSAGE_SIZE as u64 {
+            return Err(CloseCode::TooBig);
+        }
+
+        let length = length as usize;
+
+        if self.input.len() < offset + 4 + length {
+            return Ok(false);
+        }
+
+        let (start, end) = (offset + 4, offset + 4 + length);
+        let mask = [self.input[offset], self.input[offset + 1], self.input[offset + 2], self.input[offset + 3]];
+
+        for (i, byte) in self.input[start..end].iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        if opcode >= CLOSE {
+            if !fin || length > MAX_CONTROL_SIZE {
+                return Err(CloseCode::Protocol);
+            }
+
+            let mut payload = [0; MAX_CONTROL_SIZE];
+            payload[..length].copy_from_slice(&self.input[start..end]);
+            self.input.drain(..end);
+
+            return self.control(opcode, &payload[..length]).map(|_| true);
+        }
+
+        match opcode {
+            CONTINUATION => {
+                let Some((kind, compressed)) = self.fragment else {
+                    return Err(CloseCode::Protocol);
+                };
+
+                if self.fragments.len() + length > MAX_MESSAGE_SIZE {
+                    return Err(CloseCode::TooBig);
+                }
+
+                self.fragments.extend_from_slice(&self.input[start..end]);
+                self.input.drain(..end);
+
+                if fin {
+                    self.fragment = None;
+
+                    let message = message(kind, compressed, &self.fragments)?;
+                    self.fragments.clear();
+                    self.incoming.push_back(message);
+                }
+            }
+            TEXT | BINARY => {
+                if self.fragment.is_some() {
+                    return Err(CloseCode::Protocol);
+                }
+
+                if fin {
+                    let message = message(opcode, compressed, &self.input[start..end])?;
+                    self.incoming.push_back(message);
+                } else {
+                    self.fragment = Some((opcode, compressed));
+                    self.fragments.clear();
+                    self.fragments.extend_from_slice(&self.input[start..end]);
+                }
+
+                self.input.drain(..end);
+            }
+            _ => return Err(CloseCode::Protocol),
+        }
+
+        Ok(true)
+    }
+
+    fn control(&mut self, opcode: u8, payload: &[u8]) -> Result<(), CloseCode> {
+        match opcode {
+            CLOSE => {
+                let code = match payload.len() {
+                    0 => CloseCode::Normal as u16,
+                    1 => return Err(CloseCode::Protocol),
+                    _ => u16::from_be_bytes([payload[0], payload[1]]),
+                };
+
+                if !matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999) {
+                    return Err(CloseCode::Protocol);
+                }
+
+                if payload.len() > 2 && std::str::from_utf8(&payload[2..]).is_err() {
+                    return Err(CloseCode::Invalid);
+                }
+
+                if !self.closing {
+                    self.frame(CLOSE, &code.to_be_bytes());
+                    self.closing = true;
+                }
+
+                self.closed = true;
+            }
+            PING => self.frame(PONG, payload),
+            PONG => {}
+            _ => return Err(CloseCode::Protocol),
+        }
+
+        Ok(())
+    }
+
+    fn frame(&mut self, opcode: u8, payload: &[u8]) {
+        self.output.push(FIN | opcode);
+
+        match payload.len() {
+            0..=125 => self.output.push(payload.len() as u8),
+            126..=0xffff => {
+                self.output.push(126);
+                self.output.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            }
+            _ => {
+                self.output.push(127);
+                self.output.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+            }
+        }
+
+        self.output.extend_from_slice(payload);
+    }
+}
+
+pub fn accept_key(key: &[u8]) -> [u8; 28] {
+    let mut input = key.to_vec();
+    input.extend_from_slice(GUID);
+
+    let mut accept = [0; 28];
+    accept.copy_from_slice(&base64(&sha1(&input)));
+
+    accept
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+
+    for (bytes, s) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&s.to_be_bytes());
+    }
+
+    digest
+}
+
+pub fn base64(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len().div_ceil(3) * 4);
+
+    for chunk in input.chunks(3) {
+        let value = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(value >> (18 - 6 * i) & 0x3f) as usize]);
+            } else {
+                out.push(b'=');
+            }
+        }
+    }
+
+    out
+}
+
+fn message(opcode: u8, compressed: bool, payload: &[u8]) -> Result<Message, CloseCode> {
+    let payload = if compressed { inflate(payload)? } else { payload.to_vec() };
+
+    if opcode == TEXT {
+        Ok(Message::Text(String::from_utf8(payload).map_err(|_| CloseCode::Invalid)?))
+    } else {
+        Ok(Message::Binary(payload))
+    }
+}
+
+fn has_token(value: &[u8], token: &[u8]) -> bool {
+    value.split(|&b| b == b',').any(|t| t.trim_ascii().eq_ignore_ascii_case(token))
+}
+
+fn accepts_deflate(extensions: &[u8]) -> bool {
+    extensions.split(|&b| b == b',').any(|offer| {
+        let mut params = offer.split(|&b| b == b';').map(|p| p.trim_ascii());
+
+        params.next() == Some(b"permessage-deflate") && params.all(|p| {
+            match p.split(|&b| b == b'=').next() {
+                Some(b"server_max_window_bits") => p.ends_with(b"15"),
+                _ => true,
+            }
+        })
+    })
+}
+
+fn deflate(payload: &[u8]) -> Option<Vec<u8>> {
+    let mut compress = flate2::Compress::new(flate2::Compression::fast(), false);
+    let mut out = Vec::with_capacity(payload.len() / 2 + 64);
+
+    loop {
+        let consumed = compress.total_in() as usize;
+        compress.compress_vec(&payload[consumed..], &mut out, flate2::FlushCompress::Sync).ok()?;
+
+        if compress.total_in() as usize == payload.len() && out.len() < out.capacity() {
+            break;
+        }
+
+        out.reserve(out.capacity());
+    }
+
+    out.strip_suffix(&DEFLATE_TAIL).map(|o| o.to_vec())
+}
+
+fn inflate(payload: &[u8]) -> Result<Vec<u8>, CloseCode> {
+    let mut input = payload.to_vec();
+    input.extend_from_slice(&DEFLATE_TAIL);
+
+    let mut decompress = flate2::Decompress::new(false);
+    let mut out = Vec::with_capacity((payload.len() * 2 + 64).min(MAX_MESSAGE_SIZE + 1));
+
+    loop {
+        let consumed = decompress.total_in() as usize;
+        let status = decompress.decompress_vec(&input[consumed..], &mut out, flate2::FlushDecompress::Sync).map_err(|_| CloseCode::Invalid)?;
+
+        if out.len() > MAX_MESSAGE_SIZE {
+            return Err(CloseCode::TooBig);
+        }
+
+        if status == flate2::Status::StreamEnd || (decompress.total_in() as usize == input.len() && out.len() < out.capacity()) {
+            break;
+        }
+
+        out.reserve_exact(out.capacity().min(MAX_MESSAGE_SIZE + 1 - out.len()));
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_rfc_6455() {
+        assert_eq!(&accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="), b"s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn inflate_round_trips_deflate() {
+        let message = b"compressed hello ".repeat(64);
+        let compressed = deflate(&message).unwrap();
+
+        assert_eq!(inflate(&compressed).unwrap(), message);
+    }
+
+    #[test]
+    fn inflate_accepts_a_message_at_the_limit() {
+        let compressed = deflate(&vec![0; MAX_MESSAGE_SIZE]).unwrap();
+
+        assert_eq!(inflate(&compressed).unwrap().len(), MAX_MESSAGE_SIZE);
+    }
+
+    #[test]
+    fn inflate_rejects_a_message_over_the_limit() {
+        let compressed = deflate(&vec![0; MAX_MESSAGE_SIZE + 1]).unwrap();
+
+        assert_eq!(inflate(&compressed), Err(CloseCode::TooBig));
+    }
+}